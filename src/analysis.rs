@@ -0,0 +1,457 @@
+use crate::cache::Cache;
+use crate::data::{BoardGraph, Cost, CostModel, PlayerCount, Position};
+use crate::game::RAILS_PER_TURN;
+use crate::graph::{
+    self, DistanceMatrix, IncrementalMst, Metric, NotConnected, TooFar, Tree, UnGraph,
+};
+use crate::map::{Hand, Map};
+use fnv::{FnvBuildHasher, FnvHashMap};
+use rayon::prelude::*;
+use std::hash::BuildHasher;
+
+pub type MetricClosure = DistanceMatrix<Position, FnvBuildHasher>;
+
+/// A hand's cost and the Steiner points that achieve it, plus the hand.
+pub type Scored = ((usize, Vec<Position>), Hand);
+
+/// Two cities of a hand that no track can join.
+pub type Unconnected = NotConnected<Position>;
+
+/// Two nodes too far apart, under some `CostModel`, for the metric closure.
+pub type CostsTooLarge = TooFar<Position>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Solver {
+    /// `one_steiner`: fast, but sometimes a little high
+    Heuristic,
+    /// `graph::steiner_tree_exact_usize` on the board
+    Exact,
+}
+
+impl Solver {
+    pub fn name(self) -> &'static str {
+        match self {
+            Solver::Heuristic => "heuristic",
+            Solver::Exact => "exact",
+        }
+    }
+}
+
+/// The map plus everything derived from it that scoring hands needs.
+pub struct Context<'a> {
+    pub map: &'a Map,
+    pub costs: CostModel,
+    pub metric_closure: MetricClosure,
+    /// Every node's index in `metric_closure`, most central (relative to the
+    /// cities) first, so that `one_steiner` finds good Steiner points early.
+    pub candidates: Vec<u32>,
+    /// Where `score_all` keeps its results between runs, if anywhere.
+    pub cache: Option<Cache>,
+}
+
+impl<'a> Context<'a> {
+    /// Fails if `costs` put two nodes too far apart.
+    pub fn new(map: &'a Map, costs: CostModel) -> Result<Self, CostsTooLarge> {
+        let metric_closure = compute_closure(map, costs)?;
+        Ok(Self::with_closure(map, costs, metric_closure, None))
+    }
+
+    /// Like `new`, but loads the metric closure from `cache` when it's
+    /// there, and caches `score_all` too.
+    pub fn cached(map: &'a Map, costs: CostModel, cache: Cache) -> Result<Self, CostsTooLarge> {
+        let metric_closure = cache.metric_closure(map, costs, || compute_closure(map, costs))?;
+        Ok(Self::with_closure(map, costs, metric_closure, Some(cache)))
+    }
+
+    fn with_closure(
+        map: &'a Map,
+        costs: CostModel,
+        metric_closure: MetricClosure,
+        cache: Option<Cache>,
+    ) -> Self {
+        let cities = map
+            .cities
+            .iter()
+            .map(|x| metric_closure.index(x.pos).unwrap())
+            .collect::<Vec<_>>();
+        // Starting from the board's order keeps ties where they always were
+        let mut candidates = map
+            .board
+            .node_ids()
+            .map(|n| metric_closure.index(n).unwrap())
+            .collect::<Vec<_>>();
+        // Cities a node can't reach don't count against it
+        candidates.sort_by_cached_key(|&n| {
+            cities
+                .iter()
+                .filter_map(|&x| metric_closure.distance(n, x))
+                .sum::<usize>()
+        });
+
+        Self {
+            map,
+            costs,
+            metric_closure,
+            candidates,
+            cache,
+        }
+    }
+
+    /// The cost of `hand` and its Steiner points: the nodes `one_steiner`
+    /// added, or for the exact solver the branch points off the cities.
+    pub fn score(
+        &self,
+        solver: Solver,
+        hand: &[Position],
+    ) -> Result<(usize, Vec<Position>), Unconnected> {
+        match solver {
+            Solver::Heuristic => self.one_steiner(hand),
+            Solver::Exact => {
+                let (cost, tree) =
+                    graph::steiner_tree_exact_usize(&self.map.board, hand, self.costs.edge())?;
+                Ok((cost, branch_points(&tree, hand)))
+            }
+        }
+    }
+
+    /// `one_steiner` on the dense metric closure, in positions.
+    pub fn one_steiner(&self, hand: &[Position]) -> Result<(usize, Vec<Position>), Unconnected> {
+        let (mst, points) = self.one_steiner_mst(hand)?;
+        Ok((mst.weight(), points))
+    }
+
+    /// Like `one_steiner`, but with the spanning tree it found, over indices
+    /// in `metric_closure`.
+    fn one_steiner_mst(
+        &self,
+        hand: &[Position],
+    ) -> Result<(IncrementalMst<u32>, Vec<Position>), Unconnected> {
+        let terminals = hand
+            .iter()
+            .map(|&x| self.metric_closure.index(x).expect("not on the board"))
+            .collect::<Vec<_>>();
+        let id = |n| self.metric_closure.id(n);
+        let (mst, points) = one_steiner_mst(&self.metric_closure, &self.candidates, &terminals)
+            .map_err(|e| NotConnected(id(e.0), id(e.1)))?;
+        Ok((mst, points.into_iter().map(id).collect()))
+    }
+
+    /// Scores every hand dealable with `players`, in parallel, or finds a
+    /// hand that can't be connected.
+    pub fn score_all(
+        &self,
+        solver: Solver,
+        players: PlayerCount,
+    ) -> Result<Vec<Scored>, Unconnected> {
+        let compute = || {
+            self.map
+                .hands(players)
+                .par_bridge()
+                .map(|a| Ok((self.score(solver, &a)?, a)))
+                .collect()
+        };
+        match &self.cache {
+            Some(cache) => cache.scored(self.map, self.costs, solver, players, compute),
+            None => compute(),
+        }
+    }
+}
+
+fn compute_closure(map: &Map, costs: CostModel) -> Result<MetricClosure, CostsTooLarge> {
+    DistanceMatrix::from_metric_closure(&graph::metric_closure_usize(&map.board, costs.edge()))
+}
+
+/// Iterated 1-Steiner heuristic over the metric closure.
+///
+/// Repeatedly adds whichever of `candidates` most reduces the MST weight,
+/// until nothing helps.  Returns that weight and the Steiner points added,
+/// or two terminals with no path between them.
+pub fn one_steiner<I: Copy + Eq>(
+    metric: &impl Metric<I>,
+    candidates: &[I],
+    terminals: &[I],
+) -> Result<(usize, Vec<I>), NotConnected<I>> {
+    let (mst, points) = one_steiner_mst(metric, candidates, terminals)?;
+    Ok((mst.weight(), points))
+}
+
+/// `one_steiner`, keeping the spanning tree over the terminals and the
+/// Steiner points.
+fn one_steiner_mst<I: Copy + Eq>(
+    metric: &impl Metric<I>,
+    candidates: &[I],
+    terminals: &[I],
+) -> Result<(IncrementalMst<I>, Vec<I>), NotConnected<I>> {
+    let mut mst = IncrementalMst::new(metric, terminals)?;
+    let mut points = Vec::new();
+    loop {
+        let best = candidates
+            .iter()
+            .cloned()
+            .filter(|n| !terminals.contains(n) && !points.contains(n))
+            .filter_map(|n| Some((mst.weight_with(metric, n)?, n)))
+            .filter(|&(c, _)| c < mst.weight())
+            .min_by_key(|x| x.0);
+        if let Some((_, n)) = best {
+            mst.push(metric, n);
+            points.push(n);
+        } else {
+            break;
+        }
+    }
+
+    Ok((mst, points))
+}
+
+/// `one_steiner` as it was, with a Kruskal from scratch for every
+/// candidate.  Kept as the baseline for the `bench` command.
+pub fn one_steiner_from_scratch<S>(
+    metric_closure: &UnGraph<Position, (), usize, S>,
+    candidates: &[Position],
+    terminals: &[Position],
+) -> Result<(usize, Vec<Position>), Unconnected>
+where
+    S: BuildHasher + Default,
+{
+    let mut points = terminals.to_vec();
+    let mut kmst = graph::kruskal_mst_weight_usize(metric_closure, &points)?;
+    loop {
+        let best = candidates
+            .iter()
+            .cloned()
+            .filter_map(|n| {
+                if points.contains(&n) {
+                    return None;
+                }
+
+                points.push(n);
+                let c = graph::kruskal_mst_weight_usize(metric_closure, &points);
+                points.pop();
+                c.ok().filter(|&c| c < kmst).map(|c| (c, n))
+            })
+            .min_by_key(|x| x.0);
+        if let Some((c, n)) = best {
+            kmst = c;
+            points.push(n);
+        } else {
+            break;
+        }
+    }
+
+    points.drain(..terminals.len());
+    Ok((kmst, points))
+}
+
+/// A hand's heuristic network, both as abstract cost and as physical track.
+#[derive(Clone)]
+pub struct HandEvaluation {
+    pub cost: usize,
+    pub steiner_points: Vec<Position>,
+    pub track: UnGraph<Position, (), (), FnvBuildHasher>,
+}
+
+impl HandEvaluation {
+    /// Below `cost` only if the heuristic's shortest paths happen to share track.
+    pub fn track_cost(&self, g: &BoardGraph, costs: CostModel) -> usize {
+        self.track
+            .edges()
+            .filter_map(|(i, j, _)| costs.weight(g.get_edge(i, j)?.cost))
+            .sum()
+    }
+
+    /// Number of rivers and mountains (double-cost segments) the track uses.
+    pub fn crossings(&self, g: &BoardGraph) -> usize {
+        self.track
+            .edges()
+            .filter(|&(i, j, _)| g.get_edge(i, j).unwrap().cost == Cost::Two)
+            .count()
+    }
+}
+
+/// Scores `hand` and lays the result out on the board.
+///
+/// Each edge of the heuristic's spanning tree over the metric closure
+/// becomes a shortest path on the board; the exact solver's tree is already
+/// track.
+pub fn evaluate_hand(
+    ctx: &Context,
+    solver: Solver,
+    hand: &[Position],
+) -> Result<HandEvaluation, Unconnected> {
+    Ok(match solver {
+        Solver::Heuristic => {
+            let (mst, steiner_points) = ctx.one_steiner_mst(hand)?;
+            let mut track = UnGraph::with_capacity(mst.nodes().len(), 0);
+            for (a, b, _) in mst.edges() {
+                let (a, b) = (ctx.metric_closure.id(a), ctx.metric_closure.id(b));
+                let paths = graph::dijkstra_paths_usize(&ctx.map.board, a, ctx.costs.edge());
+                let path = paths.path_to(b).expect("in the metric closure but no path");
+                for x in path.windows(2) {
+                    track.try_add_node(x[0], ());
+                    track.try_add_node(x[1], ());
+                    track.try_add_edge(x[0], x[1], ());
+                }
+            }
+            if let [city] = hand {
+                track.try_add_node(*city, ());
+            }
+            HandEvaluation {
+                cost: mst.weight(),
+                steiner_points,
+                track,
+            }
+        }
+        Solver::Exact => {
+            let (cost, track) =
+                graph::steiner_tree_exact_usize(&ctx.map.board, hand, ctx.costs.edge())?;
+            HandEvaluation {
+                cost,
+                steiner_points: branch_points(&track, hand),
+                track,
+            }
+        }
+    })
+}
+
+/// Nodes other than the cities where track splits three or more ways.
+fn branch_points<S>(tree: &UnGraph<Position, (), (), S>, hand: &[Position]) -> Vec<Position>
+where
+    S: BuildHasher + Default,
+{
+    let mut points = tree
+        .node_ids()
+        .filter(|n| !hand.contains(n) && tree.neighbours(*n).len() > 2)
+        .collect::<Vec<_>>();
+    points.sort();
+    points
+}
+
+/// A place for the starting marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Start {
+    /// The exact cost of connecting the marker and the hand's cities.
+    pub cost: usize,
+    /// Turns to build that track outwards from the marker, on one of the
+    /// cheapest trees through it; only worked out for the cheapest starts,
+    /// which it tells apart.
+    pub turns: Option<usize>,
+    pub pos: Position,
+}
+
+/// Every place the starting marker could go, best first: cheapest, then
+/// quickest to build from.
+///
+/// Every node of an optimal tree is a cheapest start, so cost alone can't
+/// say whether a city or a node between them is better; the number of turns
+/// can, as a double-cost segment can't be built with a turn's last rail.
+/// Remaining ties are broken by position.
+pub fn rank_starts(
+    g: &BoardGraph,
+    costs: CostModel,
+    hand: &[Position],
+) -> Result<Vec<Start>, Unconnected> {
+    let starts = graph::steiner_costs_exact_usize(g, hand, costs.edge());
+    if starts.nodes().len() == 0 && !hand.is_empty() {
+        // Every node is left out when the cities can't all be joined
+        let reached = graph::dijkstra_usize(g, hand[0], costs.edge());
+        let city = hand.iter().find(|&&c| !reached.contains_node(c)).unwrap();
+        return Err(NotConnected(hand[0], *city));
+    }
+    let trees = graph::steiner_trees_exact_usize(g, hand, costs.edge())?;
+    let mut turns = FnvHashMap::default();
+    for tree in &trees {
+        for n in tree.node_ids() {
+            turns.entry(n).or_insert_with(|| build_turns(g, tree, n));
+        }
+    }
+    let mut ranked = starts
+        .nodes()
+        .map(|(pos, &cost)| Start {
+            cost,
+            turns: turns.get(&pos).cloned(),
+            pos,
+        })
+        .collect::<Vec<_>>();
+    ranked.sort_unstable();
+    Ok(ranked)
+}
+
+/// Turns to build `tree` outwards from `start`, each turn taking the
+/// dearest segment that still fits in its rails until none does.
+fn build_turns<S>(g: &BoardGraph, tree: &Tree<Position, S>, start: Position) -> usize
+where
+    S: BuildHasher + Default,
+{
+    let index = tree.node_ids().zip(0..).collect::<FnvHashMap<_, usize>>();
+    let mut left = tree
+        .edges()
+        .map(|(a, b, _)| (g.get_edge(a, b).and_then(|e| e.cost.rails()).unwrap(), a, b))
+        .collect::<Vec<_>>();
+    // Ties go by position, so that the order doesn't depend on hashing
+    left.sort_unstable();
+    let mut left = left
+        .into_iter()
+        .map(|(c, a, b)| (c, index[&a], index[&b]))
+        .collect::<Vec<_>>();
+    let mut built = vec![false; index.len()];
+    built[index[&start]] = true;
+    let mut turns = 0;
+    while !left.is_empty() {
+        turns += 1;
+        let mut rails = RAILS_PER_TURN;
+        while let Some(i) = left
+            .iter()
+            .rposition(|&(c, a, b)| c <= rails && (built[a] || built[b]))
+        {
+            let (c, a, b) = left.remove(i);
+            rails -= c;
+            built[a] = true;
+            built[b] = true;
+        }
+    }
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_between_cities_can_be_quicker() {
+        let map = Map::transamerica();
+        let names = [
+            "Los Angeles",
+            "Phoenix",
+            "Oklahoma City",
+            "Cincinnati",
+            "New York",
+        ];
+        let hand = names
+            .iter()
+            .map(|x| map.find_city(x).unwrap().pos)
+            .collect::<Vec<_>>();
+        let ranked = rank_starts(&map.board, CostModel::default(), &hand).unwrap();
+
+        // All 22 wherever on the tree, but the cities waste a rail
+        let best = ranked[0];
+        assert_eq!((best.cost, best.turns), (22, Some(11)));
+        assert!(!hand.contains(&best.pos));
+        for start in ranked.iter().filter(|x| hand.contains(&x.pos)) {
+            assert_eq!((start.cost, start.turns), (22, Some(12)));
+        }
+    }
+
+    #[test]
+    fn heuristic_track_costs_what_it_reports() {
+        let map = Map::transamerica();
+        let costs = CostModel::default();
+        let ctx = Context::new(&map, costs).unwrap();
+        for hand in map.hands(map.full_deck).step_by(997) {
+            let eval = evaluate_hand(&ctx, Solver::Heuristic, &hand).unwrap();
+            assert_eq!(eval.track_cost(&map.board, costs), eval.cost);
+            for city in &hand {
+                assert!(eval.track.contains_node(*city));
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use crate::analysis::HandEvaluation;
+use crate::data::{Color, Cost, Position};
+use crate::map::Map;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Double,
+    Track,
+    City(Color, bool),
+    Steiner,
+}
+
+fn ansi(style: Style) -> String {
+    match style {
+        Style::Plain => String::new(),
+        Style::Double => "\x1b[38;5;130m".to_owned(),
+        Style::Track | Style::Steiner => "\x1b[1m".to_owned(),
+        Style::City(c, false) => format!("\x1b[{}m", color_code(c)),
+        Style::City(c, true) => format!("\x1b[1;7;{}m", color_code(c)),
+    }
+}
+
+/// The SGR colour for a city, or the terminal's own for unknown colours.
+fn color_code(c: Color) -> &'static str {
+    match c.name() {
+        "green" => "32",
+        "yellow" => "33",
+        "red" => "31",
+        "blue" => "34",
+        "orange" => "38;5;208",
+        "purple" | "magenta" => "35",
+        "cyan" | "teal" => "36",
+        "pink" => "38;5;205",
+        "brown" => "38;5;94",
+        "black" | "grey" | "gray" => "90",
+        "white" => "97",
+        _ => "39",
+    }
+}
+
+pub const LEGEND: &str = "\
+. junction   --- / \\ one rail   === # two rails (river or mountain)
+*** track    @ Steiner point    de a city, as keyed above; DE one in the hand";
+
+/// A two-letter label for each of `map.cities`, in order, unique on the map:
+/// the name's initial and the first of its later letters still free.
+fn labels(map: &Map) -> Vec<String> {
+    let mut used = Vec::<String>::new();
+    for city in &map.cities {
+        let letters = city
+            .name
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<Vec<_>>();
+        let first = letters.first().cloned().unwrap_or('?');
+        let label = letters
+            .iter()
+            .skip(1)
+            .cloned()
+            .chain('a'..='z')
+            .chain('2'..='9')
+            .map(|c| [first, c].iter().collect::<String>())
+            .find(|x| !used.contains(x))
+            .expect("too many cities with one initial");
+        used.push(label);
+    }
+    used
+}
+
+/// Draws the board as text, north up, each node four columns from its
+/// `Right` neighbour and two rows from the ones above it.
+///
+/// Cities show as two letters over the node and the track to its right,
+/// keyed below the map; with `hand`, its cities are capitalised and its
+/// track drawn in `*`.  `color` adds ANSI colours by `data::Color`.
+pub fn render(map: &Map, hand: Option<(&[Position], &HandEvaluation)>, color: bool) -> String {
+    let board = &map.board;
+    let col_of = |p: Position| 4 * p.0 as isize - 2 * p.1 as isize;
+    let min_col = board.node_ids().map(col_of).min().unwrap_or(0);
+    let max_col = board.node_ids().map(col_of).max().unwrap_or(0);
+    let max_y = board.node_ids().map(|p| p.1).max().unwrap_or(0);
+    let cell = |p: Position| (2 * (max_y - p.1) as usize, (col_of(p) - min_col) as usize);
+
+    // One more column for the last letter of a city in the east
+    let width = (max_col - min_col) as usize + 2;
+    let mut grid = vec![vec![(' ', Style::Plain); width]; 2 * max_y as usize + 1];
+
+    for (a, b, e) in board.edges() {
+        debug_assert!(a.shares_line(b));
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        let on_track = hand.is_some_and(|h| h.1.track.contains_edge(a, b));
+        let ((r, c), (r2, c2)) = (cell(a), cell(b));
+        let style = if on_track {
+            Style::Track
+        } else if e.cost == Cost::Two {
+            Style::Double
+        } else {
+            Style::Plain
+        };
+        if r == r2 {
+            let glyph = match style {
+                Style::Track => '*',
+                Style::Double => '=',
+                _ => '-',
+            };
+            for x in &mut grid[r][c.min(c2) + 1..c.max(c2)] {
+                *x = (glyph, style);
+            }
+        } else {
+            // b is north of a, so one row up, halfway across
+            let glyph = match (style, c2 > c) {
+                (Style::Track, _) => '*',
+                (Style::Double, _) => '#',
+                (_, true) => '/',
+                (_, false) => '\\',
+            };
+            grid[r - 1][(c + c2) / 2] = (glyph, style);
+        }
+    }
+
+    let labels = labels(map);
+    let label = |i: usize| {
+        let in_hand = hand.is_some_and(|h| h.0.contains(&map.cities[i].pos));
+        let label = if in_hand {
+            labels[i].to_uppercase()
+        } else {
+            labels[i].clone()
+        };
+        (label, Style::City(map.cities[i].color, in_hand))
+    };
+    for n in board.node_ids() {
+        let (r, c) = cell(n);
+        grid[r][c] = if hand.is_some_and(|h| h.1.steiner_points.contains(&n)) {
+            ('@', Style::Steiner)
+        } else {
+            ('.', Style::Plain)
+        };
+    }
+    for (i, city) in map.cities.iter().enumerate() {
+        let (r, c) = cell(city.pos);
+        let (label, style) = label(i);
+        for (x, ch) in grid[r][c..].iter_mut().zip(label.chars()) {
+            *x = (ch, style);
+        }
+    }
+
+    let mut out = String::new();
+    for row in grid {
+        let mut current = Style::Plain;
+        let mut line = String::new();
+        for (ch, style) in row {
+            if color && style != current {
+                if current != Style::Plain {
+                    line.push_str("\x1b[0m");
+                }
+                line.push_str(&ansi(style));
+                current = style;
+            }
+            line.push(ch);
+        }
+        if color && current != Style::Plain {
+            line.push_str("\x1b[0m");
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    let mut key = (0..map.cities.len()).collect::<Vec<_>>();
+    key.sort_by_key(|&i| &labels[i]);
+    let entry = map.cities.iter().map(|c| c.name.len()).max().unwrap_or(0) + 5;
+    let per_line = (width / entry).max(1);
+    out.push('\n');
+    for line in key.chunks(per_line) {
+        let mut text = String::new();
+        for &i in line {
+            let (label, style) = label(i);
+            if color {
+                text.push_str(&format!("{}{}\x1b[0m", ansi(style), label));
+            } else {
+                text.push_str(&label);
+            }
+            text.push_str(&format!(
+                " {:width$}",
+                map.cities[i].name,
+                width = entry - 3
+            ));
+        }
+        out.push_str(text.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_file;
+    use crate::game::Network;
+
+    #[test]
+    fn small_board() {
+        let map = board_file::parse(
+            "small",
+            "column 0: 111 1..\n\
+             column 1: 2..\n\
+             city 0,0 red Denver\n\
+             city 1,1 blue Dallas\n\
+             city 2,0 red dashed Detroit\n",
+        )
+        .unwrap();
+        assert_eq!(
+            render(&map, None, false),
+            "\
+.---da
+ \\ /
+  de--.===dt
+
+da Dallas
+de Denver
+dt Detroit
+"
+        );
+
+        let mut track = Network::with_capacity(0, 0);
+        for &p in &[Position(0, 0), Position(1, 1), Position(0, 1)] {
+            track.try_add_node(p, ());
+        }
+        track.try_add_edge(Position(0, 0), Position(0, 1), ());
+        track.try_add_edge(Position(0, 1), Position(1, 1), ());
+        let hand = [Position(0, 0), Position(1, 1)];
+        let eval = HandEvaluation {
+            cost: 2,
+            steiner_points: vec![Position(0, 1)],
+            track,
+        };
+        assert_eq!(
+            render(&map, Some((&hand, &eval)), false),
+            "\
+@***DA
+ * /
+  DE--.===dt
+
+DA Dallas
+DE Denver
+dt Detroit
+"
+        );
+    }
+
+    #[test]
+    fn labels_are_unique() {
+        let map = Map::transamerica();
+        let mut labels = labels(&map);
+        labels.sort();
+        labels.dedup();
+        assert_eq!(labels.len(), map.cities.len());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::data::{self, BoardGraph, City, Color, Cost, PlayerCount, Position};
+use crate::map::Map;
+use itertools::Itertools;
+
+/// The built-in board, in the format `parse` reads.  See the comments at the
+/// top of the file for a description of the format.
+pub const DEFAULT: &str = include_str!("../boards/transamerica.txt");
+
+/// Reads a board file, naming the map after the file.
+pub fn load(path: &str) -> Result<Map, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let name = std::path::Path::new(path)
+        .file_stem()
+        .map_or(path.into(), |x| x.to_string_lossy());
+    parse(&name, &text).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Reads a board description, reporting the first problem found.
+///
+/// City names are leaked, so that loaded cities can be used like the
+/// built-in ones; a map is only loaded once per run.
+pub fn parse(name: &str, text: &str) -> Result<Map, String> {
+    let mut graph = BoardGraph::with_capacity(0, 0);
+    let mut columns = Vec::new();
+    let mut cities = Vec::<City>::new();
+    let mut colors = None;
+    let mut full_deck = PlayerCount::Four;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let fail = |message: String| format!("line {}: {}", number + 1, message);
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("column ") {
+            let (x, cells) = rest
+                .split_once(':')
+                .ok_or_else(|| fail("expected `column <x>: <cells>`".to_string()))?;
+            let x = coordinate(x.trim()).map_err(fail)?;
+            if columns.contains(&x) {
+                return Err(fail(format!("column {} appears twice", x)));
+            }
+            columns.push(x);
+            for (y, cell) in cells.split_whitespace().enumerate() {
+                let costs = parse_cell(cell).map_err(|e| fail(format!("{}, {}: {}", x, y, e)))?;
+                if y >= usize::from(u8::MAX) {
+                    return Err(fail(format!("column {} is too tall", x)));
+                }
+                data::add_cell(&mut graph, Position(x, y as u8), costs);
+            }
+        } else if let Some(rest) = line.strip_prefix("city ") {
+            let mut words = rest.split_whitespace();
+            let pos = words
+                .next()
+                .and_then(|p| p.split_once(','))
+                .ok_or_else(|| fail("expected `city <x>,<y> <color> <name>`".to_string()))?;
+            let pos = Position(
+                coordinate(pos.0).map_err(fail)?,
+                coordinate(pos.1).map_err(fail)?,
+            );
+            let color = words
+                .next()
+                .ok_or_else(|| fail("missing colour".to_string()))
+                .and_then(|c| parse_color(c).map_err(fail))?;
+            let mut name = words.collect::<Vec<_>>();
+            let dashed = name.first() == Some(&"dashed");
+            if dashed {
+                name.remove(0);
+            }
+            if name.is_empty() {
+                return Err(fail("missing city name".to_string()));
+            }
+            let name = name.join(" ");
+            if let Some(c) = cities.iter().find(|c| c.pos == pos || c.name == name) {
+                return Err(fail(format!("{} is already at {:?}", c.name, c.pos)));
+            }
+            cities.push(City {
+                pos,
+                color,
+                name: Box::leak(name.into_boxed_str()),
+                dashed,
+            });
+        } else if let Some(rest) = line.strip_prefix("colors ") {
+            let list = rest
+                .split_whitespace()
+                .map(parse_color)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(fail)?;
+            if let Some((_, c)) = list.iter().enumerate().find(|x| list[..x.0].contains(x.1)) {
+                return Err(fail(format!("{:?} is listed twice", c)));
+            }
+            colors = Some(list);
+        } else if let Some(rest) = line.strip_prefix("full-deck ") {
+            full_deck = rest
+                .trim()
+                .parse()
+                .ok()
+                .and_then(PlayerCount::new)
+                .ok_or_else(|| fail(format!("full-deck takes 2 to 6, not {:?}", rest)))?;
+        } else {
+            return Err(fail(format!("unknown line {:?}", line)));
+        }
+    }
+
+    if let Some(c) = cities.iter().find(|c| !graph.contains_node(c.pos)) {
+        return Err(format!("{} at {:?} is not on the board", c.name, c.pos));
+    }
+    let colors = match colors {
+        Some(list) => list,
+        None => cities.iter().map(|c| c.color).unique().collect(),
+    };
+    if let Some(c) = cities.iter().find(|c| !colors.contains(&c.color)) {
+        return Err(format!("{} is {:?}, which is not dealt", c.name, c.color));
+    }
+    if let Some(c) = colors
+        .iter()
+        .find(|&&c| !cities.iter().any(|x| x.color == c))
+    {
+        return Err(format!("no city is {:?}", c));
+    }
+    Ok(Map {
+        name: name.to_owned(),
+        board: graph,
+        cities,
+        colors,
+        full_deck,
+    })
+}
+
+fn coordinate(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        Ok(x) if x < u8::MAX => Ok(x),
+        _ => Err(format!("bad coordinate {:?}", s)),
+    }
+}
+
+fn parse_cell(cell: &str) -> Result<[Cost; 3], String> {
+    let mut costs = [Cost::Inf; 3];
+    if cell.chars().count() != costs.len() {
+        return Err(format!("cell {:?} is not three characters", cell));
+    }
+    for (cost, ch) in costs.iter_mut().zip(cell.chars()) {
+        *cost = match ch {
+            '.' => Cost::Inf,
+            '0' => Cost::Zero,
+            '1' => Cost::One,
+            '2' => Cost::Two,
+            _ => return Err(format!("unknown cost {:?}", ch)),
+        };
+    }
+    Ok(costs)
+}
+
+/// Any word names a colour, except `dashed`, which would be ambiguous in a
+/// `city` line.
+fn parse_color(s: &str) -> Result<Color, String> {
+    if s.eq_ignore_ascii_case("dashed") || !s.chars().all(|c| c.is_alphabetic() || c == '-') {
+        return Err(format!("bad colour name {:?}", s));
+    }
+    Ok(Color::named(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{make_board, CITIES};
+
+    #[test]
+    fn default_matches_built_in() {
+        let map = parse("transamerica", DEFAULT).unwrap();
+        let built_in = make_board();
+
+        let mut nodes = map.board.node_ids().collect::<Vec<_>>();
+        let mut expected = built_in.node_ids().collect::<Vec<_>>();
+        nodes.sort();
+        expected.sort();
+        assert_eq!(nodes, expected);
+
+        assert_eq!(map.board.edges().len(), built_in.edges().len());
+        for (a, b, e) in built_in.edges() {
+            assert_eq!(map.board.get_edge(a, b).map(|x| x.cost), Some(e.cost));
+        }
+
+        assert_eq!(map.cities.len(), CITIES.len());
+        for (c, expected) in map.cities.iter().zip(CITIES) {
+            assert_eq!(
+                (c.pos, c.color, c.name, c.dashed),
+                (expected.pos, expected.color, expected.name, expected.dashed)
+            );
+        }
+        assert_eq!(
+            map.colors,
+            [
+                Color::GREEN,
+                Color::RED,
+                Color::YELLOW,
+                Color::BLUE,
+                Color::ORANGE
+            ]
+        );
+        assert_eq!(map.full_deck, PlayerCount::Four);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse("test", "column 0: 1x1").err().unwrap(),
+            "line 1: 0, 0: unknown cost 'x'"
+        );
+        assert_eq!(
+            parse("test", "column 0: 111\n\ncity 5,5 red Nowhere")
+                .err()
+                .unwrap(),
+            "Nowhere at P(5,5) is not on the board"
+        );
+        assert_eq!(
+            parse("test", "column 0: 111\ncity 0,0 dashed Somewhere")
+                .err()
+                .unwrap(),
+            "line 2: bad colour name \"dashed\""
+        );
+        assert_eq!(
+            parse(
+                "test",
+                "column 0: 111\ncity 0,0 red Somewhere\ncolors red blue"
+            )
+            .err()
+            .unwrap(),
+            "no city is Blue"
+        );
+    }
+
+    #[test]
+    fn maps_choose_their_colours() {
+        let text = "column 0: 111 111 11.\ncolumn 1: 1.1 1.1 1..\n\
+                    city 0,0 Purple Lilac\ncity 1,0 red Rouge\ncity 0,1 pink Rose\n\
+                    city 1,1 red dashed Carmine\ncity 0,2 teal Sea";
+        let map = parse("test", text).unwrap();
+        assert_eq!(format!("{:?}", map.colors), "[Purple, Red, Pink, Teal]");
+        assert_eq!(map.colors[0], Color::named("purple"));
+
+        let hands = map.hands(PlayerCount::Six).collect::<Vec<_>>();
+        assert_eq!(hands.len(), 2);
+        assert!(hands.iter().all(|h| h.len() == 4));
+        assert_eq!(map.city_names(&hands[0]), ["Lilac", "Rouge", "Rose", "Sea"]);
+        assert_eq!(map.hands(PlayerCount::Three).count(), 1);
+    }
+}
@@ -5,6 +5,7 @@ pub trait Bag {
     fn clear(&mut self);
     fn push(&mut self, x: Self::Item);
     fn pop(&mut self) -> Option<Self::Item>;
+    fn is_empty(&self) -> bool;
 }
 
 impl<T> Bag for Vec<T> {
@@ -18,6 +19,9 @@ impl<T> Bag for Vec<T> {
     fn pop(&mut self) -> Option<T> {
         self.pop()
     }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
 }
 
 impl<T> Bag for VecDeque<T> {
@@ -31,6 +35,9 @@ impl<T> Bag for VecDeque<T> {
     fn pop(&mut self) -> Option<T> {
         self.pop_front()
     }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
 }
 
 impl<T: Ord> Bag for std::collections::BinaryHeap<T> {
@@ -44,6 +51,9 @@ impl<T: Ord> Bag for std::collections::BinaryHeap<T> {
     fn pop(&mut self) -> Option<T> {
         self.pop()
     }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -72,6 +82,22 @@ impl<B: Bag> CustomBucketQueue<B> {
         self.len += 1;
     }
 
+    /// Like `push`, but `priority` may be below the front.  The buckets are
+    /// extended forwards to it, reusing empty ones from the back.
+    pub fn push_any(&mut self, priority: usize, extra: B::Item) where B: Default {
+        while priority < self.front {
+            match self.data.back() {
+                Some(last) if last.is_empty() => {
+                    let last = self.data.pop_back().unwrap();
+                    self.data.push_front(last);
+                }
+                _ => self.data.push_front(Default::default()),
+            }
+            self.front -= 1;
+        }
+        self.push(priority, extra);
+    }
+
     pub fn pop(&mut self) -> Option<(usize, B::Item)> {
         loop {
             let first = self.data.front_mut()?;
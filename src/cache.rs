@@ -0,0 +1,307 @@
+use crate::analysis::{MetricClosure, Scored, Solver};
+use crate::data::{CostModel, PlayerCount, Position};
+use crate::map::Map;
+use fnv::FnvHasher;
+use std::convert::TryFrom;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the file layout or what the files hold changes.  A
+/// solver scoring hands differently bumps `SOLVER_VERSION` instead.
+const VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"TAHC";
+
+/// Bumped whenever either solver's costs or Steiner points change for some
+/// hand, so that hands scored by an older solver are scored again.
+const SOLVER_VERSION: u32 = 1;
+
+/// A directory of metric closures and scored hands from earlier runs.
+///
+/// Files are named by a hash of everything that went into them (the board,
+/// cities and cost model, plus the solver, its version and the player count
+/// for hands), and start with that hash again, so a stale or damaged file is
+/// just recomputed and overwritten.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Only a successful `compute` is stored.
+    pub fn metric_closure<E>(
+        &self,
+        map: &Map,
+        costs: CostModel,
+        compute: impl FnOnce() -> Result<MetricClosure, E>,
+    ) -> Result<MetricClosure, E> {
+        let key = map_key(map, costs);
+        self.get_or_insert("closure", key, compute, write_closure, read_closure)
+    }
+
+    /// Only a successful `compute` is stored.
+    pub fn scored<E>(
+        &self,
+        map: &Map,
+        costs: CostModel,
+        solver: Solver,
+        players: PlayerCount,
+        compute: impl FnOnce() -> Result<Vec<Scored>, E>,
+    ) -> Result<Vec<Scored>, E> {
+        let key = scored_key(map, costs, solver, players);
+        self.get_or_insert(
+            "hands",
+            key,
+            compute,
+            |w, x| write_scored(w, x),
+            read_scored,
+        )
+    }
+
+    fn path(&self, kind: &str, key: u64) -> PathBuf {
+        self.dir.join(format!("{}-{:016x}.bin", kind, key))
+    }
+
+    fn get<T>(&self, kind: &str, key: u64, read: fn(&mut &[u8]) -> Option<T>) -> Option<T> {
+        let bytes = std::fs::read(self.path(kind, key)).ok()?;
+        decode(&bytes, key, read)
+    }
+
+    fn get_or_insert<T, E>(
+        &self,
+        kind: &str,
+        key: u64,
+        compute: impl FnOnce() -> Result<T, E>,
+        write: fn(&mut Vec<u8>, &T) -> Option<()>,
+        read: fn(&mut &[u8]) -> Option<T>,
+    ) -> Result<T, E> {
+        if let Some(x) = self.get(kind, key, read) {
+            return Ok(x);
+        }
+
+        let x = compute()?;
+        let path = self.path(kind, key);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&key.to_le_bytes());
+        let stored = match write(&mut bytes, &x) {
+            Some(()) => store(&path, &bytes),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "too large")),
+        };
+        if let Err(e) = stored {
+            eprintln!("warning: not caching {}: {}", path.display(), e);
+        }
+        Ok(x)
+    }
+}
+
+fn decode<T>(mut bytes: &[u8], key: u64, read: fn(&mut &[u8]) -> Option<T>) -> Option<T> {
+    let bytes = &mut bytes;
+    if take(bytes, 4)? != MAGIC || read_u32(bytes)? != VERSION || read_u64(bytes)? != key {
+        return None;
+    }
+    let x = read(bytes)?;
+    if bytes.is_empty() {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+/// Writes to a temporary file first, so that an interrupted or concurrent
+/// run never leaves a half-written cache behind.
+fn store(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&temp, bytes)?;
+    std::fs::rename(&temp, path)
+}
+
+fn scored_key(map: &Map, costs: CostModel, solver: Solver, players: PlayerCount) -> u64 {
+    let mut hasher = FnvHasher::with_key(map_key(map, costs));
+    hasher.write(solver.name().as_bytes());
+    hasher.write_u32(SOLVER_VERSION);
+    hasher.write_u8(players.get() as u8);
+    hasher.finish()
+}
+
+/// Hashes the map's contents (not its name) with the cost model.
+fn map_key(map: &Map, costs: CostModel) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write_u32(VERSION);
+
+    let mut edges = map
+        .board
+        .edges()
+        .map(|(a, b, e)| (a, b, format!("{:?}", e.cost)))
+        .collect::<Vec<_>>();
+    edges.sort();
+    hasher.write_u64(map.board.nodes().len() as u64);
+    hasher.write_u64(edges.len() as u64);
+    for (a, b, cost) in edges {
+        hasher.write(&[a.0, a.1, b.0, b.1]);
+        hasher.write(cost.as_bytes());
+    }
+
+    let mut cities = map.cities.iter().collect::<Vec<_>>();
+    cities.sort_by_key(|x| x.pos);
+    for c in cities {
+        hasher.write(&[c.pos.0, c.pos.1, c.dashed as u8]);
+        hasher.write(format!("{:?} {}\0", c.color, c.name).as_bytes());
+    }
+    for c in &map.colors {
+        hasher.write(format!("{:?}\0", c).as_bytes());
+    }
+    hasher.write_u8(map.full_deck.get() as u8);
+
+    for &w in &[costs.zero, costs.one, costs.two] {
+        hasher.write_u64(w as u64);
+    }
+    hasher.finish()
+}
+
+fn write_closure(out: &mut Vec<u8>, closure: &MetricClosure) -> Option<()> {
+    out.extend_from_slice(&u32::try_from(closure.len()).ok()?.to_le_bytes());
+    for &p in closure.ids() {
+        out.extend_from_slice(&[p.0, p.1]);
+    }
+    for &d in closure.table() {
+        out.extend_from_slice(&d.to_le_bytes());
+    }
+    Some(())
+}
+
+fn read_closure(bytes: &mut &[u8]) -> Option<MetricClosure> {
+    let len = read_u32(bytes)? as usize;
+    let ids = (0..len)
+        .map(|_| read_position(bytes))
+        .collect::<Option<Vec<_>>>()?;
+    let table = take(bytes, 2 * len.checked_mul(len)?)?
+        .chunks(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]))
+        .collect();
+    MetricClosure::from_table(ids, table)
+}
+
+fn write_scored(out: &mut Vec<u8>, hands: &[Scored]) -> Option<()> {
+    out.extend_from_slice(&u32::try_from(hands.len()).ok()?.to_le_bytes());
+    for ((cost, points), hand) in hands {
+        out.extend_from_slice(&u32::try_from(*cost).ok()?.to_le_bytes());
+        for list in &[points, hand] {
+            out.push(u8::try_from(list.len()).ok()?);
+            for &p in list.iter() {
+                out.extend_from_slice(&[p.0, p.1]);
+            }
+        }
+    }
+    Some(())
+}
+
+fn read_scored(bytes: &mut &[u8]) -> Option<Vec<Scored>> {
+    let positions = |bytes: &mut &[u8]| {
+        let len = take(bytes, 1)?[0];
+        (0..len)
+            .map(|_| read_position(bytes))
+            .collect::<Option<Vec<_>>>()
+    };
+    (0..read_u32(bytes)?)
+        .map(|_| {
+            let cost = read_u32(bytes)? as usize;
+            let points = positions(bytes)?;
+            Some(((cost, points), positions(bytes)?))
+        })
+        .collect()
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if bytes.len() < n {
+        return None;
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Some(head)
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let b = take(bytes, 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Option<u64> {
+    let b = take(bytes, 8)?;
+    let mut x = [0; 8];
+    x.copy_from_slice(b);
+    Some(u64::from_le_bytes(x))
+}
+
+fn read_position(bytes: &mut &[u8]) -> Option<Position> {
+    let b = take(bytes, 2)?;
+    Some(Position(b[0], b[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Context;
+
+    #[test]
+    fn round_trips_and_rejects_stale_files() {
+        let dir = std::env::temp_dir().join(format!("transamerica-cache-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let map = Map::transamerica();
+        let costs = CostModel::default();
+        let fresh = Context::new(&map, costs).unwrap().metric_closure;
+        let closure = |x: &MetricClosure| Ok::<_, ()>(x.clone());
+
+        let stored = cache
+            .metric_closure(&map, costs, || closure(&fresh))
+            .unwrap();
+        let loaded = cache
+            .metric_closure(&map, costs, || -> Result<_, ()> { panic!("not cached") })
+            .unwrap();
+        assert_eq!(loaded.ids(), fresh.ids());
+        assert_eq!(loaded.table(), stored.table());
+
+        let other = CostModel { two: 3, ..costs };
+        assert_ne!(map_key(&map, costs), map_key(&map, other));
+        let mut recomputed = false;
+        cache
+            .metric_closure(&map, other, || {
+                recomputed = true;
+                closure(&fresh)
+            })
+            .unwrap();
+        assert!(recomputed);
+
+        let hands: Vec<Scored> = vec![((7, vec![Position(3, 4)]), vec![Position(1, 2)])];
+        let players = PlayerCount::Two;
+        let failed = cache.scored(&map, costs, Solver::Exact, players, || Err(()));
+        assert_eq!(failed, Err(()));
+        let ok = |x: &Vec<Scored>| Ok::<_, ()>(x.clone());
+        cache
+            .scored(&map, costs, Solver::Exact, players, || ok(&hands))
+            .unwrap();
+        let loaded = cache.scored(&map, costs, Solver::Exact, players, || -> Result<_, ()> {
+            panic!()
+        });
+        assert_eq!(loaded, Ok(hands.clone()));
+
+        // A truncated file is recomputed rather than trusted
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let bytes = std::fs::read(&path).unwrap();
+            std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        }
+        let loaded = cache.scored(&map, costs, Solver::Exact, players, || ok(&vec![]));
+        assert_eq!(loaded, Ok(vec![]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,496 @@
+use crate::analysis::Solver;
+use crate::data::{CostModel, PlayerCount};
+use crate::graph::MAX_DISTANCE;
+use crate::weight::Fixed;
+
+pub const USAGE: &str = "\
+usage: transamerica-hand-test [options] [command]
+
+commands:
+    analyze                  every hand, overall and per city (the default)
+    histogram                overall distribution of hand costs only
+    city <name>              distribution of hands holding one city
+    hand <city> <city> ...   cost of one specific hand (quote two-word names)
+    fairness                 quantiles, skew, cheap hands and marginal cost
+                             per city and colour
+    pairs [file]             which cities of different colours combine well
+                             or badly; with a file, also an SVG heatmap
+                             of the pairs' effects
+    gap                      compare the heuristic against the exact solver
+    bench                    time the heuristic's search for Steiner points,
+                             with and without incremental MSTs and the dense
+                             metric closure
+    starts                   where starting markers should go
+    deal [deals]             hand costs under the real deal for 2-6 players,
+                             and the spread between the best and worst hands
+                             at the table over random deals (default 100000)
+    simulate [rounds]        play the built-in strategies against each other
+    route <city> <city>      cheapest track between two cities
+    board [<city> ...]       the board as text, with a hand's track if given
+    validate                 check the map for transcription mistakes
+    render [dir]             SVGs of the best, median and worst hands,
+                             overall and per city (default dir: render)
+
+options:
+    --map <name>             a built-in map (only transamerica, the default,
+                             so far), or a board file like
+                             boards/transamerica.txt
+    --players <2-6>          use the deck for this many players (default 6)
+    --solver <name>          heuristic (default) or exact
+    --costs <a>,<b>,<c>      what free, plain and river or mountain segments
+                             count for when scoring hands (default 0,1,2);
+                             route also takes fractions, like 0,1,1.5
+    --format <name>          text (default), or for analyze, histogram and
+                             city: csv or jsonl (a row per hand) or json
+                             (a summary with per-city statistics); for
+                             fairness: csv (a row per city); for pairs: csv
+                             (the matrix of effects) or jsonl (a row per
+                             pair, with its mean and effect)
+    --track                  include each hand's track in csv and jsonl
+    --color <when>           ANSI colours for board: auto (default), always
+                             or never
+    --cache <dir>            keep metric closures and scored hands here between
+                             runs (default .transamerica-cache)
+    --no-cache               neither read nor write the cache
+    --threads <n>            worker threads (default: one per core)
+    --seed <n>               random seed for deal and simulate (default 0)
+    -h, --help               show this message";
+
+const COMMANDS: &[&str] = &[
+    "analyze",
+    "histogram",
+    "city",
+    "hand",
+    "fairness",
+    "pairs",
+    "gap",
+    "bench",
+    "starts",
+    "deal",
+    "simulate",
+    "route",
+    "render",
+    "board",
+    "validate",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Analyze,
+    Histogram,
+    City(String),
+    Hand(Vec<String>),
+    Fairness,
+    Pairs(Option<String>),
+    Gap,
+    Bench,
+    Starts,
+    Deal(usize),
+    Simulate(usize),
+    Route(String, String),
+    Render(String),
+    Board(Vec<String>),
+    Validate,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    /// One row per hand
+    Csv,
+    /// One JSON object per hand, one per line
+    JsonLines,
+    /// A single summary document
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// A built-in map name or a board file
+    pub map: String,
+    pub players: PlayerCount,
+    pub solver: Solver,
+    pub costs: CostModel,
+    /// `--costs` with fractions, which only `route` takes; `costs` is then
+    /// left at its default
+    pub fractional_costs: Option<CostModel<Fixed>>,
+    pub format: Format,
+    /// Include each hand's track in CSV and JSON Lines rows
+    pub track: bool,
+    /// `None` to colour only when writing to a terminal
+    pub color: Option<bool>,
+    /// Where to cache results between runs, or `None` not to
+    pub cache: Option<String>,
+    pub threads: Option<usize>,
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            map: "transamerica".to_owned(),
+            players: PlayerCount::Six,
+            solver: Solver::Heuristic,
+            costs: CostModel::default(),
+            fractional_costs: None,
+            format: Format::Text,
+            track: false,
+            color: None,
+            cache: Some(".transamerica-cache".to_owned()),
+            threads: None,
+            seed: 0,
+        }
+    }
+}
+
+/// Parses everything after the program name.  Options may appear anywhere.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<(Command, Options), String> {
+    let mut options = Options::default();
+    let mut words = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok((Command::Help, options)),
+            "--map" => options.map = value("--map")?,
+            "--players" => {
+                let n = value("--players")?;
+                options.players = n
+                    .parse()
+                    .ok()
+                    .and_then(PlayerCount::new)
+                    .ok_or_else(|| format!("--players takes 2 to 6, not {:?}", n))?;
+            }
+            "--solver" => {
+                options.solver = match value("--solver")?.as_str() {
+                    "heuristic" => Solver::Heuristic,
+                    "exact" => Solver::Exact,
+                    x => return Err(format!("unknown solver {:?}", x)),
+                }
+            }
+            "--costs" => {
+                let list = value("--costs")?;
+                let weights = list
+                    .split(',')
+                    .map(|x| x.trim().parse().ok().and_then(Fixed::from_f64))
+                    .collect::<Option<Vec<_>>>();
+                let costs = match weights.as_deref() {
+                    Some(&[zero, one, two]) => CostModel { zero, one, two },
+                    _ => return Err(format!("--costs takes three numbers, not {:?}", list)),
+                };
+                // Caught here, before a metric closure spends its time and
+                // memory on distances it can't keep
+                if let Some(x) = [costs.zero, costs.one, costs.two]
+                    .iter()
+                    .find(|x| x.0 > MAX_DISTANCE as u64 * Fixed::ONE.0)
+                {
+                    return Err(format!(
+                        "--costs too large: {} is more than {} can be stored",
+                        x, MAX_DISTANCE
+                    ));
+                }
+                let whole = |x: Fixed| x.0.is_multiple_of(Fixed::ONE.0);
+                if whole(costs.zero) && whole(costs.one) && whole(costs.two) {
+                    let units = |x: Fixed| (x.0 / Fixed::ONE.0) as usize;
+                    options.costs = CostModel {
+                        zero: units(costs.zero),
+                        one: units(costs.one),
+                        two: units(costs.two),
+                    };
+                    options.fractional_costs = None;
+                } else {
+                    options.costs = CostModel::default();
+                    options.fractional_costs = Some(costs);
+                }
+            }
+            "--format" => {
+                options.format = match value("--format")?.as_str() {
+                    "text" => Format::Text,
+                    "csv" => Format::Csv,
+                    "jsonl" => Format::JsonLines,
+                    "json" => Format::Json,
+                    x => return Err(format!("unknown format {:?}", x)),
+                }
+            }
+            "--track" => options.track = true,
+            "--color" => {
+                options.color = match value("--color")?.as_str() {
+                    "auto" => None,
+                    "always" => Some(true),
+                    "never" => Some(false),
+                    x => return Err(format!("unknown colour setting {:?}", x)),
+                }
+            }
+            "--cache" => options.cache = Some(value("--cache")?),
+            "--no-cache" => options.cache = None,
+            "--threads" => {
+                let n = value("--threads")?;
+                options.threads = Some(
+                    n.parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("bad thread count {:?}", n))?,
+                );
+            }
+            "--seed" => {
+                let n = value("--seed")?;
+                options.seed = n.parse().map_err(|_| format!("bad seed {:?}", n))?;
+            }
+            x if x.starts_with('-') => return Err(format!("unknown option {:?}", x)),
+            _ => words.push(arg),
+        }
+    }
+
+    let command = match words.first().map(String::as_str) {
+        None => Command::Analyze,
+        Some("analyze") if words.len() == 1 => Command::Analyze,
+        Some("histogram") if words.len() == 1 => Command::Histogram,
+        Some("fairness") if words.len() == 1 => Command::Fairness,
+        Some("gap") if words.len() == 1 => Command::Gap,
+        Some("bench") if words.len() == 1 => Command::Bench,
+        Some("starts") if words.len() == 1 => Command::Starts,
+        Some("validate") if words.len() == 1 => Command::Validate,
+        Some("city") if words.len() >= 2 => Command::City(words[1..].join(" ")),
+        Some("hand") if words.len() >= 2 => Command::Hand(words[1..].to_vec()),
+        Some("deal") if words.len() <= 2 => Command::Deal(match words.get(1) {
+            Some(n) => n
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("bad deal count {:?}", n))?,
+            None => 100_000,
+        }),
+        Some("simulate") if words.len() <= 2 => Command::Simulate(match words.get(1) {
+            Some(n) => n
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("bad round count {:?}", n))?,
+            None => 100,
+        }),
+        Some("pairs") if words.len() <= 2 => Command::Pairs(words.get(1).cloned()),
+        Some("route") if words.len() == 3 => Command::Route(words[1].clone(), words[2].clone()),
+        Some("board") => Command::Board(words[1..].to_vec()),
+        Some("render") if words.len() <= 2 => {
+            Command::Render(words.get(1).cloned().unwrap_or_else(|| "render".to_owned()))
+        }
+        Some(x) if COMMANDS.contains(&x) => return Err(format!("bad arguments for {}", x)),
+        Some(x) => return Err(format!("unknown command {:?}", x)),
+    };
+    let format_ok = match command {
+        Command::Analyze | Command::Histogram | Command::City(_) => true,
+        Command::Fairness => options.format == Format::Csv,
+        Command::Pairs(_) => matches!(options.format, Format::Csv | Format::JsonLines),
+        _ => false,
+    };
+    if options.format != Format::Text && !format_ok {
+        return Err(
+            "--format only applies to analyze, histogram and city, csv to fairness, or csv \
+             and jsonl to pairs"
+                .to_owned(),
+        );
+    }
+    if options.fractional_costs.is_some() && !matches!(command, Command::Route(..)) {
+        return Err("only route takes --costs with fractions".to_owned());
+    }
+    Ok((command, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<(Command, Options), String> {
+        parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn costs() {
+        let (_, options) = parse_str("--costs 0,1,3").unwrap();
+        assert_eq!(
+            options.costs,
+            CostModel {
+                zero: 0,
+                one: 1,
+                two: 3
+            }
+        );
+        assert_eq!(options.fractional_costs, None);
+        // Whole numbers written with decimals are still whole
+        let (_, options) = parse_str("--costs 0,1.0,2.000").unwrap();
+        assert_eq!(options.costs, CostModel::default());
+
+        let (command, options) = parse_str("--costs 0,1,1.5 route Denver Boston").unwrap();
+        assert_eq!(command, Command::Route("Denver".into(), "Boston".into()));
+        let expected = CostModel {
+            zero: Fixed(0),
+            one: Fixed(1000),
+            two: Fixed(1500),
+        };
+        assert_eq!(options.fractional_costs, Some(expected));
+        assert_eq!(options.costs, CostModel::default());
+        assert!(parse_str("--costs 0,1,1.5 histogram").is_err());
+
+        for bad in &[
+            "0,1",
+            "0,1,2,3",
+            "0,-1,2",
+            "0,x,2",
+            "0,1,NaN",
+            "0,1,inf",
+            "0,1,1.0004",
+        ] {
+            let err = parse_str(&format!("--costs {} route A B", bad)).unwrap_err();
+            assert!(err.starts_with("--costs takes three numbers"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn costs_too_large_for_a_closure() {
+        let err = parse_str("--costs 0,1,1000000000 histogram").unwrap_err();
+        assert!(err.starts_with("--costs too large"), "{}", err);
+        assert!(parse_str("--costs 0,1,65535").is_err());
+        assert!(parse_str("--costs 0,1,65534.5 route A B").is_err());
+        let (_, options) = parse_str("--costs 0,1,65534").unwrap();
+        assert_eq!(options.costs.two, MAX_DISTANCE);
+    }
+
+    #[test]
+    fn commands() {
+        let command = |args| parse_str(args).unwrap().0;
+        assert_eq!(command(""), Command::Analyze);
+        assert_eq!(command("analyze"), Command::Analyze);
+        assert_eq!(command("histogram"), Command::Histogram);
+        assert_eq!(command("fairness"), Command::Fairness);
+        assert_eq!(command("gap"), Command::Gap);
+        assert_eq!(command("bench"), Command::Bench);
+        assert_eq!(command("starts"), Command::Starts);
+        assert_eq!(command("validate"), Command::Validate);
+        assert_eq!(
+            command("city Salt Lake City"),
+            Command::City("Salt Lake City".into())
+        );
+        assert_eq!(
+            command("hand Denver Boston"),
+            Command::Hand(vec!["Denver".into(), "Boston".into()])
+        );
+        assert_eq!(command("deal"), Command::Deal(100_000));
+        assert_eq!(command("deal 7"), Command::Deal(7));
+        assert_eq!(command("simulate"), Command::Simulate(100));
+        assert_eq!(command("simulate 7"), Command::Simulate(7));
+        assert_eq!(command("pairs"), Command::Pairs(None));
+        assert_eq!(
+            command("pairs Denver"),
+            Command::Pairs(Some("Denver".into()))
+        );
+        assert_eq!(
+            command("route Denver Boston"),
+            Command::Route("Denver".into(), "Boston".into())
+        );
+        assert_eq!(command("render"), Command::Render("render".into()));
+        assert_eq!(command("render out"), Command::Render("out".into()));
+        assert_eq!(command("board"), Command::Board(vec![]));
+        assert_eq!(
+            command("board export x"),
+            Command::Board(vec!["export".into(), "x".into()])
+        );
+        // Help stops parsing, whatever follows
+        assert_eq!(command("histogram -h --players 9"), Command::Help);
+        assert_eq!(command("--help nonsense"), Command::Help);
+    }
+
+    #[test]
+    fn options() {
+        let (command, options) = parse_str(
+            "--map my.board city --players 3 --solver exact Denver --track --color always \
+             --cache c --threads 4 --seed 12 --format csv",
+        )
+        .unwrap();
+        assert_eq!(command, Command::City("Denver".into()));
+        assert_eq!(options.map, "my.board");
+        assert_eq!(options.players, PlayerCount::Three);
+        assert_eq!(options.solver, Solver::Exact);
+        assert_eq!(options.format, Format::Csv);
+        assert!(options.track);
+        assert_eq!(options.color, Some(true));
+        assert_eq!(options.cache, Some("c".into()));
+        assert_eq!(options.threads, Some(4));
+        assert_eq!(options.seed, 12);
+
+        let (_, options) = parse_str("--cache c --no-cache --color never").unwrap();
+        assert_eq!(options.cache, None);
+        assert_eq!(options.color, Some(false));
+        let (_, options) = parse_str("--color always --color auto --solver heuristic").unwrap();
+        assert_eq!(options.color, None);
+        assert_eq!(options.solver, Solver::Heuristic);
+        assert_eq!(
+            parse_str("--format jsonl analyze").unwrap().1.format,
+            Format::JsonLines
+        );
+        assert_eq!(
+            parse_str("--format json histogram").unwrap().1.format,
+            Format::Json
+        );
+        assert_eq!(
+            parse_str("--format text gap").unwrap().1.format,
+            Format::Text
+        );
+        assert!(parse_str("--format csv fairness").is_ok());
+        assert!(parse_str("--format csv pairs").is_ok());
+        assert!(parse_str("--format jsonl pairs Denver").is_ok());
+    }
+
+    #[test]
+    fn errors() {
+        let err = |args| parse_str(args).unwrap_err();
+        assert_eq!(err("--players"), "--players needs a value");
+        assert_eq!(err("--seed"), "--seed needs a value");
+        assert_eq!(err("--players 1"), "--players takes 2 to 6, not \"1\"");
+        assert_eq!(err("--players 7"), "--players takes 2 to 6, not \"7\"");
+        assert_eq!(err("--players six"), "--players takes 2 to 6, not \"six\"");
+        assert_eq!(err("--solver best"), "unknown solver \"best\"");
+        assert_eq!(err("--format xml"), "unknown format \"xml\"");
+        assert_eq!(err("--color blue"), "unknown colour setting \"blue\"");
+        assert_eq!(err("--threads 0"), "bad thread count \"0\"");
+        assert_eq!(err("--threads -1"), "bad thread count \"-1\"");
+        assert_eq!(err("--seed x"), "bad seed \"x\"");
+        assert_eq!(err("--verbose"), "unknown option \"--verbose\"");
+        assert_eq!(err("deal 0"), "bad deal count \"0\"");
+        assert_eq!(err("deal many"), "bad deal count \"many\"");
+        assert_eq!(err("simulate 0"), "bad round count \"0\"");
+        assert_eq!(err("simulate -3"), "unknown option \"-3\"");
+        assert_eq!(err("solve"), "unknown command \"solve\"");
+        for args in &[
+            "analyze x",
+            "histogram x",
+            "fairness x",
+            "gap x",
+            "bench x",
+            "starts x",
+            "validate x",
+            "city",
+            "hand",
+            "deal 1 2",
+            "simulate 1 2",
+            "pairs a b",
+            "route a",
+            "route a b c",
+            "render a b",
+        ] {
+            let command = args.split(' ').next().unwrap();
+            assert_eq!(err(args), format!("bad arguments for {}", command));
+        }
+
+        for args in &[
+            "--format csv gap",
+            "--format json fairness",
+            "--format jsonl fairness",
+            "--format json pairs",
+            "--format csv route a b",
+            "--format csv hand Denver",
+        ] {
+            assert!(err(args).starts_with("--format only applies"), "{}", args);
+        }
+    }
+}
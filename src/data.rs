@@ -1,18 +1,52 @@
-use itertools::iproduct;
 use std::hash::{Hash, Hasher};
 use std::ops::Add;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum Color {
-    Green,
-    Yellow,
-    Red,
-    Blue,
-    Orange,
+/// A colour of city card, by its lower-case name.  Each map chooses its own
+/// colours; see `Map::colors`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Color(&'static str);
+
+impl Color {
+    pub const GREEN: Color = Color("green");
+    pub const YELLOW: Color = Color("yellow");
+    pub const RED: Color = Color("red");
+    pub const BLUE: Color = Color("blue");
+    pub const ORANGE: Color = Color("orange");
+
+    /// The colour called `name`, ignoring case.  Names other than the
+    /// constants' are leaked, like loaded city names.
+    pub fn named(name: &str) -> Color {
+        let name = name.to_lowercase();
+        let known = [
+            Self::GREEN,
+            Self::YELLOW,
+            Self::RED,
+            Self::BLUE,
+            Self::ORANGE,
+        ];
+        match known.iter().find(|c| c.0 == name) {
+            Some(&c) => c,
+            None => Color(Box::leak(name.into_boxed_str())),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.0
+    }
 }
-use self::Color::*;
 
-#[derive(Debug, Clone, Copy)]
+/// Capitalised, as in `Green`.
+impl std::fmt::Debug for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut chars = self.0.chars();
+        if let Some(first) = chars.next() {
+            write!(f, "{}", first.to_uppercase())?;
+        }
+        f.write_str(chars.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct City {
     pub pos: Position,
     pub color: Color,
@@ -41,66 +75,97 @@ impl Position {
     pub fn shares_line(self, other: Position) -> bool {
         self.0 == other.0 || self.1 == other.1 || (self.0 + other.1) == (self.1 + other.0)
     }
+
+    /// True if `other` is one step away in one of the six `Direction`s.
+    pub fn is_adjacent(self, other: Position) -> bool {
+        [Right, UpRight, UpLeft]
+            .iter()
+            .any(|&d| self + d == other || other + d == self)
+    }
+
+    /// The neighbours in the directions board files give edges in, so that
+    /// each pair of neighbours comes up once.
+    pub fn forward_neighbours(self) -> [Position; 3] {
+        [self + Right, self + UpRight, self + UpLeft]
+    }
+
+    /// Where this sits on the real (triangular) board, in edge lengths, with
+    /// y pointing north: `Right` is due east, `UpRight` and `UpLeft` are 60°
+    /// either side of north.
+    pub fn planar(self) -> (f64, f64) {
+        let (x, y) = (f64::from(self.0), f64::from(self.1));
+        (x - y / 2.0, y * 3f64.sqrt() / 2.0)
+    }
 }
 
 #[rustfmt::skip]
 pub const CITIES: &[City] = &[
-    City { pos: Position(0, 2), color: Green, name: "San Diego", dashed: true },
-    City { pos: Position(0, 3), color: Green, name: "Los Angeles", dashed: false },
-    City { pos: Position(0, 6), color: Green, name: "San Francisco", dashed: false },
-    City { pos: Position(1, 7), color: Green, name: "Sacramento", dashed: false },
-    City { pos: Position(2, 9), color: Green, name: "Medford", dashed: false },
-    City { pos: Position(3, 11), color: Green, name: "Portland", dashed: false },
-    City { pos: Position(4, 12), color: Green, name: "Seattle", dashed: true },
-
-    City { pos: Position(4, 4), color: Yellow, name: "Santa Fe", dashed: false },
-    City { pos: Position(4, 8), color: Yellow, name: "Salt Lake City", dashed: false },
-    City { pos: Position(6, 7), color: Yellow, name: "Denver", dashed: true },
-    City { pos: Position(7, 4), color: Yellow, name: "Oklahoma City", dashed: false },
-    City { pos: Position(9, 6), color: Yellow, name: "Kansas City", dashed: true },
-    City { pos: Position(9, 8), color: Yellow, name: "Omaha", dashed: false },
-    City { pos: Position(11, 6), color: Yellow, name: "St. Louis", dashed: false },
-
-    City { pos: Position(2, 3), color: Red, name: "Phoenix", dashed: false },
-    City { pos: Position(3, 1), color: Red, name: "El Paso", dashed: false },
-    City { pos: Position(6, 0), color: Red, name: "Houston", dashed: true },
-    City { pos: Position(7, 2), color: Red, name: "Dallas", dashed: false },
-    City { pos: Position(8, 0), color: Red, name: "New Orleans", dashed: false },
-    City { pos: Position(10, 3), color: Red, name: "Memphis", dashed: false },
-    City { pos: Position(11, 2), color: Red, name: "Atlanta", dashed: true },
-
-    City { pos: Position(6, 11), color: Blue, name: "Helena", dashed: false },
-    City { pos: Position(10, 11), color: Blue, name: "Bismark", dashed: false },
-    City { pos: Position(12, 10), color: Blue, name: "Minneapolis", dashed: false },
-    City { pos: Position(13, 11), color: Blue, name: "Duluth", dashed: true },
-    City { pos: Position(14, 7), color: Blue, name: "Cincinnati", dashed: false },
-    City { pos: Position(14, 9), color: Blue, name: "Chicago", dashed: false },
-    City { pos: Position(17, 10), color: Blue, name: "Buffalo", dashed: true },
-
-    City { pos: Position(11, 0), color: Orange, name: "Jacksonville", dashed: false },
-    City { pos: Position(13, 2), color: Orange, name: "Charleston", dashed: false },
-    City { pos: Position(13, 4), color: Orange, name: "Winston", dashed: false },
-    City { pos: Position(15, 5), color: Orange, name: "Richmond", dashed: true },
-    City { pos: Position(16, 7), color: Orange, name: "Washington", dashed: false },
-    City { pos: Position(17, 8), color: Orange, name: "New York", dashed: false },
-    City { pos: Position(19, 10), color: Orange, name: "Boston", dashed: true },
+    City { pos: Position(0, 2), color: Color::GREEN, name: "San Diego", dashed: true },
+    City { pos: Position(0, 3), color: Color::GREEN, name: "Los Angeles", dashed: false },
+    City { pos: Position(0, 6), color: Color::GREEN, name: "San Francisco", dashed: false },
+    City { pos: Position(1, 7), color: Color::GREEN, name: "Sacramento", dashed: false },
+    City { pos: Position(2, 9), color: Color::GREEN, name: "Medford", dashed: false },
+    City { pos: Position(3, 11), color: Color::GREEN, name: "Portland", dashed: false },
+    City { pos: Position(4, 12), color: Color::GREEN, name: "Seattle", dashed: true },
+
+    City { pos: Position(4, 4), color: Color::YELLOW, name: "Santa Fe", dashed: false },
+    City { pos: Position(4, 8), color: Color::YELLOW, name: "Salt Lake City", dashed: false },
+    City { pos: Position(6, 7), color: Color::YELLOW, name: "Denver", dashed: true },
+    City { pos: Position(7, 4), color: Color::YELLOW, name: "Oklahoma City", dashed: false },
+    City { pos: Position(9, 6), color: Color::YELLOW, name: "Kansas City", dashed: true },
+    City { pos: Position(9, 8), color: Color::YELLOW, name: "Omaha", dashed: false },
+    City { pos: Position(11, 6), color: Color::YELLOW, name: "St. Louis", dashed: false },
+
+    City { pos: Position(2, 3), color: Color::RED, name: "Phoenix", dashed: false },
+    City { pos: Position(3, 1), color: Color::RED, name: "El Paso", dashed: false },
+    City { pos: Position(6, 0), color: Color::RED, name: "Houston", dashed: true },
+    City { pos: Position(7, 2), color: Color::RED, name: "Dallas", dashed: false },
+    City { pos: Position(8, 0), color: Color::RED, name: "New Orleans", dashed: false },
+    City { pos: Position(10, 3), color: Color::RED, name: "Memphis", dashed: false },
+    City { pos: Position(11, 2), color: Color::RED, name: "Atlanta", dashed: true },
+
+    City { pos: Position(6, 11), color: Color::BLUE, name: "Helena", dashed: false },
+    City { pos: Position(10, 11), color: Color::BLUE, name: "Bismark", dashed: false },
+    City { pos: Position(12, 10), color: Color::BLUE, name: "Minneapolis", dashed: false },
+    City { pos: Position(13, 11), color: Color::BLUE, name: "Duluth", dashed: true },
+    City { pos: Position(14, 7), color: Color::BLUE, name: "Cincinnati", dashed: false },
+    City { pos: Position(14, 9), color: Color::BLUE, name: "Chicago", dashed: false },
+    City { pos: Position(17, 10), color: Color::BLUE, name: "Buffalo", dashed: true },
+
+    City { pos: Position(11, 0), color: Color::ORANGE, name: "Jacksonville", dashed: false },
+    City { pos: Position(13, 2), color: Color::ORANGE, name: "Charleston", dashed: false },
+    City { pos: Position(13, 4), color: Color::ORANGE, name: "Winston", dashed: false },
+    City { pos: Position(15, 5), color: Color::ORANGE, name: "Richmond", dashed: true },
+    City { pos: Position(16, 7), color: Color::ORANGE, name: "Washington", dashed: false },
+    City { pos: Position(17, 8), color: Color::ORANGE, name: "New York", dashed: false },
+    City { pos: Position(19, 10), color: Color::ORANGE, name: "Boston", dashed: true },
 ];
 
-fn cities(c: Color, d: Option<bool>) -> impl DoubleEndedIterator<Item = Position> + Clone {
-    CITIES
-        .iter()
-        .filter(move |x| x.color == c && (d.is_none() || Some(x.dashed) == d))
-        .map(|x| x.pos)
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PlayerCount {
+    Two = 2,
+    Three = 3,
+    Four = 4,
+    Five = 5,
+    Six = 6,
 }
 
-pub fn hands(d: Option<bool>) -> impl Iterator<Item = [Position; 5]> + Clone {
-    iproduct!(
-        cities(Green, d),
-        cities(Red, d),
-        cities(Yellow, d),
-        cities(Blue, d),
-        cities(Orange, d)
-    ).map(|(c0, c1, c2, c3, c4)| [c0, c1, c2, c3, c4])
+impl PlayerCount {
+    pub const ALL: [PlayerCount; 5] = [
+        PlayerCount::Two,
+        PlayerCount::Three,
+        PlayerCount::Four,
+        PlayerCount::Five,
+        PlayerCount::Six,
+    ];
+
+    pub fn new(n: usize) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|&p| p.get() == n)
+    }
+
+    pub fn get(self) -> usize {
+        self as usize
+    }
 }
 
 pub type BoardGraph = crate::graph::UnGraph<Position, (), Edge, fnv::FnvBuildHasher>;
@@ -114,18 +179,51 @@ pub enum Cost {
 }
 use self::Cost::*;
 
-impl Add<Cost> for usize {
-    type Output = usize;
-    fn add(self, other: Cost) -> usize {
-        self + match other {
-            Zero => 0,
-            One => 1,
-            Two => 2,
-            _ => unimplemented!(),
+impl Cost {
+    /// Rail units under the printed rules, or `None` if it can't be built.
+    pub fn rails(self) -> Option<usize> {
+        CostModel::default().weight(self)
+    }
+}
+
+/// What each kind of segment counts for when scoring hands, so house rules
+/// like mountains costing three can be tried.  `Inf` is never buildable.
+///
+/// Hands are only scored in whole numbers; `route` also takes `Fixed`
+/// costs, for mountains costing 1.5.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CostModel<C = usize> {
+    pub zero: C,
+    pub one: C,
+    pub two: C,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            zero: 0,
+            one: 1,
+            two: 2,
         }
     }
 }
 
+impl<C: Copy> CostModel<C> {
+    pub fn weight(&self, cost: Cost) -> Option<C> {
+        match cost {
+            Zero => Some(self.zero),
+            One => Some(self.one),
+            Two => Some(self.two),
+            Inf => None,
+        }
+    }
+
+    /// The weight of a board edge, ready to hand to the `graph` functions.
+    pub fn edge(self) -> impl Fn(&Edge) -> Option<C> + Copy {
+        move |e| self.weight(e.cost)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 enum Direction {
     Right,
@@ -456,16 +554,21 @@ pub fn make_board() -> BoardGraph {
     ];
     for (c, i) in columns.iter().zip(0..) {
         for (r, j) in c.iter().zip(0..) {
-            let n = Position(i, j);
-            for (k, d) in [Right, UpRight, UpLeft].iter().cloned().enumerate() {
-                if r[k] != Inf {
-                    //println!("{:?} <-> {:?}: {:?}", n, n + d, r[k]);
-                    g.try_add_node(n, ());
-                    g.try_add_node(n + d, ());
-                    g.add_edge(n, n + d, Edge { cost: r[k] });
-                }
-            }
+            add_cell(&mut g, Position(i, j), *r);
         }
     }
     g
 }
+
+/// Adds the edges from `n` to its `Right`, `UpRight` and `UpLeft`
+/// neighbours, in that order, leaving out the `Inf` ones.
+pub fn add_cell(g: &mut BoardGraph, n: Position, costs: [Cost; 3]) {
+    for (k, d) in [Right, UpRight, UpLeft].iter().cloned().enumerate() {
+        if costs[k] != Inf {
+            //println!("{:?} <-> {:?}: {:?}", n, n + d, costs[k]);
+            g.try_add_node(n, ());
+            g.try_add_node(n + d, ());
+            g.add_edge(n, n + d, Edge { cost: costs[k] });
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::data::PlayerCount;
+use crate::map::{Hand, Map};
+use crate::rng::Rng;
+use crate::stats::Distribution;
+use fnv::FnvHashMap;
+
+/// Checks that every colour has a city for each player to be dealt.
+pub fn check(map: &Map, players: PlayerCount) -> Result<(), String> {
+    let dealable = |c| map.deck(players).filter(|x| x.color == c).count();
+    match map.colors.iter().find(|&&c| dealable(c) < players.get()) {
+        Some(c) => Err(format!(
+            "not enough {:?} cities to deal {} hands",
+            c,
+            players.get()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Deals like the game: each colour's deck is shuffled and every player
+/// draws one card from each, so no two players share a city.
+///
+/// `check` must have passed for `players`.
+pub fn deal(map: &Map, players: PlayerCount, rng: &mut Rng) -> Vec<Hand> {
+    let mut decks = map
+        .colors
+        .iter()
+        .map(|&color| {
+            let mut deck = map
+                .deck(players)
+                .filter(|c| c.color == color)
+                .map(|c| c.pos)
+                .collect::<Vec<_>>();
+            rng.shuffle(&mut deck);
+            deck
+        })
+        .collect::<Vec<_>>();
+    (0..players.get())
+        .map(|_| decks.iter_mut().map(|d| d.pop().unwrap()).collect())
+        .collect()
+}
+
+/// The distributions of hand costs under the real deal for one player count.
+pub struct Deals {
+    pub players: PlayerCount,
+    /// Any one player's hand, exactly: every shuffle is equally likely, so
+    /// each seat's hand is uniform over the player count's `Map::hands`.
+    pub hand: Distribution,
+    /// Dearest minus cheapest hand at the table, from `deals` random deals.
+    /// Drawing without replacement ties the hands together, and there are
+    /// far too many deals to enumerate.
+    pub spread: Distribution,
+    pub deals: usize,
+}
+
+/// Both distributions for `players`, with `costs` holding the cost of every
+/// hand dealable from the map's full deck.
+pub fn deals(
+    map: &Map,
+    costs: &FnvHashMap<Hand, usize>,
+    players: PlayerCount,
+    deals: usize,
+    rng: &mut Rng,
+) -> Result<Deals, String> {
+    check(map, players)?;
+    let hand = map.hands(players).map(|x| costs[&x]).collect();
+    let spread = (0..deals)
+        .map(|_| {
+            let table = deal(map, players, rng)
+                .iter()
+                .map(|x| costs[x])
+                .collect::<Vec<_>>();
+            table.iter().max().unwrap() - table.iter().min().unwrap()
+        })
+        .collect();
+    Ok(Deals {
+        players,
+        hand,
+        spread,
+        deals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deals_are_without_replacement() {
+        let map = Map::transamerica();
+        let mut rng = Rng::new(1);
+        for &players in &PlayerCount::ALL {
+            check(&map, players).unwrap();
+            for _ in 0..20 {
+                let hands = deal(&map, players, &mut rng);
+                assert_eq!(hands.len(), players.get());
+                let mut cities = hands.concat();
+                for hand in &hands {
+                    assert_eq!(hand.len(), map.colors.len());
+                    for (&p, &c) in hand.iter().zip(&map.colors) {
+                        let city = map.city_at(p).unwrap();
+                        assert!(city.color == c && map.allows(players, city));
+                    }
+                }
+                cities.sort();
+                cities.dedup();
+                assert_eq!(cities.len(), players.get() * map.colors.len());
+            }
+        }
+    }
+}
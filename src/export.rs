@@ -0,0 +1,279 @@
+use crate::data::{City, Position};
+use crate::fairness::{self, Fairness};
+use crate::map::Map;
+use crate::pairs::Interactions;
+use crate::stats::ParallelVariance;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// One hand's results, ready to be written out.
+pub struct HandRow<'a> {
+    pub hand: &'a [Position],
+    pub cost: usize,
+    pub steiner_points: &'a [Position],
+    /// The physical track, if it was computed.
+    pub track: Option<&'a [(Position, Position)]>,
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_position(p: Position) -> String {
+    format!("[{},{}]", p.0, p.1)
+}
+
+fn json_list<T>(xs: impl IntoIterator<Item = T>, f: impl Fn(T) -> String) -> String {
+    let items = xs.into_iter().map(f).collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+/// NaN and infinities aren't JSON, so they become null.
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        x.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
+
+/// Positions in CSV are `x:y`, and lists of them are space-separated.
+fn csv_position(p: Position) -> String {
+    format!("{}:{}", p.0, p.1)
+}
+
+pub fn write_csv_header(w: &mut impl Write, map: &Map) -> io::Result<()> {
+    for i in 1..=map.colors.len() {
+        write!(w, "city{0},color{0},position{0},", i)?;
+    }
+    writeln!(w, "cost,steiner_points,track")
+}
+
+pub fn write_csv_row(w: &mut impl Write, map: &Map, row: &HandRow) -> io::Result<()> {
+    for &p in row.hand {
+        let city = map.city_at(p).expect("not a city");
+        write!(
+            w,
+            "{},{:?},{},",
+            csv_field(city.name),
+            city.color,
+            csv_position(p)
+        )?;
+    }
+    let points = row
+        .steiner_points
+        .iter()
+        .map(|&p| csv_position(p))
+        .collect::<Vec<_>>();
+    let track = row
+        .track
+        .unwrap_or(&[])
+        .iter()
+        .map(|&(a, b)| format!("{}-{}", csv_position(a), csv_position(b)))
+        .collect::<Vec<_>>();
+    writeln!(w, "{},{},{}", row.cost, points.join(" "), track.join(" "))
+}
+
+/// One JSON object per line; `track` is left out when it wasn't computed.
+pub fn write_jsonl_row(w: &mut impl Write, map: &Map, row: &HandRow) -> io::Result<()> {
+    let cities = row
+        .hand
+        .iter()
+        .map(|&p| map.city_at(p).expect("not a city"))
+        .collect::<Vec<_>>();
+    write!(
+        w,
+        "{{\"cities\":{},\"colors\":{},\"positions\":{},\"cost\":{},\"steiner_points\":{}",
+        json_list(&cities, |c| json_string(c.name)),
+        json_list(&cities, |c| json_string(&format!("{:?}", c.color))),
+        json_list(row.hand.iter().cloned(), json_position),
+        row.cost,
+        json_list(row.steiner_points.iter().cloned(), json_position),
+    )?;
+    if let Some(track) = row.track {
+        write!(
+            w,
+            ",\"track\":{}",
+            json_list(track, |&(a, b)| format!(
+                "[{},{}]",
+                json_position(a),
+                json_position(b)
+            ))
+        )?;
+    }
+    writeln!(w, "}}")
+}
+
+/// Statistics for the hands holding one city.
+pub struct CitySummary<'a> {
+    pub city: &'a City,
+    pub hands: usize,
+    pub stats: ParallelVariance,
+}
+
+/// A single JSON document with the overall distribution and per-city stats.
+pub fn write_summary_json(
+    w: &mut impl Write,
+    players: usize,
+    solver: &str,
+    hands: usize,
+    stats: ParallelVariance,
+    histogram: &BTreeMap<usize, usize>,
+    cities: &[CitySummary],
+) -> io::Result<()> {
+    writeln!(w, "{{")?;
+    writeln!(w, "  \"players\": {},", players)?;
+    writeln!(w, "  \"solver\": {},", json_string(solver))?;
+    writeln!(w, "  \"hands\": {},", hands)?;
+    writeln!(w, "  \"mean\": {},", json_number(stats.mean()))?;
+    writeln!(w, "  \"stdev\": {},", json_number(stats.stdev_pop()))?;
+    let buckets = histogram
+        .iter()
+        .map(|(k, v)| format!("\"{}\": {}", k, v))
+        .collect::<Vec<_>>();
+    writeln!(w, "  \"histogram\": {{{}}},", buckets.join(", "))?;
+    if cities.is_empty() {
+        writeln!(w, "  \"cities\": []")?;
+        return writeln!(w, "}}");
+    }
+    writeln!(w, "  \"cities\": [")?;
+    for (i, c) in cities.iter().enumerate() {
+        writeln!(
+            w,
+            "    {{\"name\": {}, \"color\": {}, \"position\": {}, \"dashed\": {}, \
+             \"hands\": {}, \"mean\": {}, \"stdev\": {}}}{}",
+            json_string(c.city.name),
+            json_string(&format!("{:?}", c.city.color)),
+            json_position(c.city.pos),
+            c.city.dashed,
+            c.hands,
+            json_number(c.stats.mean()),
+            json_number(c.stats.stdev_pop()),
+            if i + 1 < cities.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(w, "  ]")?;
+    writeln!(w, "}}")
+}
+
+/// One row per city, with every `fairness` metric.
+pub fn write_fairness_csv(w: &mut impl Write, f: &Fairness) -> io::Result<()> {
+    writeln!(
+        w,
+        "city,color,hands,mean,marginal,stdev,skewness,kurtosis,p10,p25,p50,p75,p90,cheap"
+    )?;
+    for c in &f.cities {
+        let m = c.costs.moments;
+        write!(
+            w,
+            "{},{:?},{},{},{},{},{},{}",
+            csv_field(c.city.name),
+            c.city.color,
+            c.costs.counts.len(),
+            m.mean(),
+            c.marginal,
+            m.stdev_pop(),
+            m.skewness(),
+            m.kurtosis()
+        )?;
+        for &p in &fairness::QUANTILES {
+            write!(w, ",{}", c.costs.counts.quantile(p).unwrap())?;
+        }
+        writeln!(w, ",{}", c.cheap)?;
+    }
+    Ok(())
+}
+
+/// The matrix of pair effects, with a row and a column per city; pairs that
+/// can't be dealt together are left empty.  This is what the heatmap shows.
+pub fn write_pairs_csv(w: &mut impl Write, x: &Interactions) -> io::Result<()> {
+    write!(w, "city")?;
+    for c in &x.cities {
+        write!(w, ",{}", csv_field(c.name))?;
+    }
+    writeln!(w)?;
+    for (c, row) in x.cities.iter().zip(&x.pairs) {
+        write!(w, "{}", csv_field(c.name))?;
+        for pair in row {
+            match pair {
+                Some(p) => write!(w, ",{}", p.effect)?,
+                None => write!(w, ",")?,
+            }
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// One JSON object per pair of cities that can be dealt together, with how
+/// many hands hold both, their mean cost and the pair's effect.
+pub fn write_pairs_jsonl(w: &mut impl Write, x: &Interactions) -> io::Result<()> {
+    for (i, j, p) in x.unique_pairs() {
+        writeln!(
+            w,
+            "{{\"cities\":[{},{}],\"hands\":{},\"mean\":{},\"effect\":{}}}",
+            json_string(x.cities[i].name),
+            json_string(x.cities[j].name),
+            p.hands,
+            json_number(p.mean),
+            json_number(p.effect)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(csv_field("Denver"), "Denver");
+        assert_eq!(csv_field("Washington, D.C."), "\"Washington, D.C.\"");
+        assert_eq!(csv_field("the \"Big\" Apple"), "\"the \"\"Big\"\" Apple\"");
+        assert_eq!(csv_field("back\\slash"), "back\\slash");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\r\nlines"), "\"two\r\nlines\"");
+    }
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string("Denver"), "\"Denver\"");
+        assert_eq!(json_string("Washington, D.C."), "\"Washington, D.C.\"");
+        assert_eq!(
+            json_string("the \"Big\" Apple"),
+            "\"the \\\"Big\\\" Apple\""
+        );
+        assert_eq!(json_string("back\\slash"), "\"back\\\\slash\"");
+        assert_eq!(json_string("tab\tand\u{1}"), "\"tab\\u0009and\\u0001\"");
+        assert_eq!(json_string("Zürich"), "\"Zürich\"");
+    }
+
+    #[test]
+    fn json_numbers() {
+        assert_eq!(json_number(12.5), "12.5");
+        assert_eq!(json_number(-3.0), "-3");
+        // JSON has no NaN or infinities
+        assert_eq!(json_number(f64::NAN), "null");
+        assert_eq!(json_number(f64::INFINITY), "null");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::analysis::Scored;
+use crate::data::{City, Color, Position};
+use crate::map::Map;
+use crate::stats::{Distribution, ParallelVariance};
+use fnv::FnvHashMap;
+use rayon::prelude::*;
+
+/// The quantiles reported for every distribution.
+pub const QUANTILES: [f64; 5] = [0.1, 0.25, 0.5, 0.75, 0.9];
+
+/// Hands at or below this quantile of all hands count as cheap.
+pub const CHEAP: f64 = 0.1;
+
+pub struct CityFairness<'a> {
+    pub city: &'a City,
+    pub costs: Distribution,
+    /// Fraction of the hands holding this city that are cheap.
+    pub cheap: f64,
+    /// Mean cost of hands with this city minus hands with another city of
+    /// its colour instead, the other colours held fixed.
+    pub marginal: f64,
+}
+
+pub struct ColorFairness<'a> {
+    pub color: Color,
+    /// The colour's cheapest and dearest cities, by mean cost.
+    pub best: &'a City,
+    pub worst: &'a City,
+    /// How far apart the mean costs of the colour's cities are.
+    pub spread: f64,
+    pub marginal_stdev: f64,
+}
+
+pub struct Fairness<'a> {
+    pub overall: Distribution,
+    /// The highest cost that is still cheap.
+    pub cheap_cutoff: usize,
+    pub cities: Vec<CityFairness<'a>>,
+    pub colors: Vec<ColorFairness<'a>>,
+}
+
+/// Balance metrics for every city and colour in `hands`, which must be
+/// every hand dealable from `map`, like `Context::score_all` gives.
+pub fn fairness<'a>(map: &'a Map, hands: &[Scored]) -> Fairness<'a> {
+    let (overall, by_city) = hands
+        .par_iter()
+        .fold(
+            || (Distribution::default(), FnvHashMap::default()),
+            |(overall, mut by_city), ((cost, _), hand)| {
+                for &c in hand {
+                    let d: &mut Distribution = by_city.entry(c).or_default();
+                    *d = Distribution::merge(std::mem::take(d), (*cost).into());
+                }
+                (Distribution::merge(overall, (*cost).into()), by_city)
+            },
+        )
+        .reduce(
+            || (Distribution::default(), FnvHashMap::default()),
+            |(a, mut a_cities), (b, b_cities)| {
+                for (c, d) in b_cities {
+                    let e: &mut Distribution = a_cities.entry(c).or_default();
+                    *e = Distribution::merge(std::mem::take(e), d);
+                }
+                (Distribution::merge(a, b), a_cities)
+            },
+        );
+    let by_city: FnvHashMap<Position, Distribution> = by_city;
+    let cheap_cutoff = overall.counts.quantile(CHEAP).unwrap_or(0);
+
+    // Every hand is in the Cartesian product, so each choice of the other
+    // colours appears once with each city of this colour, and comparing
+    // against the rest of the colour's hands holds the others fixed.
+    let cities = map
+        .cities
+        .iter()
+        .filter_map(|city| {
+            let costs = by_city.get(&city.pos)?.clone();
+            let others = map
+                .cities
+                .iter()
+                .filter(|x| x.color == city.color && x.pos != city.pos)
+                .filter_map(|x| by_city.get(&x.pos))
+                .map(|x| x.moments)
+                .fold(ParallelVariance::default(), ParallelVariance::merge);
+            Some(CityFairness {
+                city,
+                cheap: costs.counts.fraction_at_most(cheap_cutoff),
+                marginal: costs.moments.mean() - others.mean(),
+                costs,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let colors = map
+        .colors
+        .iter()
+        .filter_map(|&color| {
+            let of_color = cities
+                .iter()
+                .filter(|x| x.city.color == color)
+                .collect::<Vec<_>>();
+            let mean = |x: &&&CityFairness| x.costs.moments.mean();
+            let best = of_color
+                .iter()
+                .min_by(|a, b| mean(a).partial_cmp(&mean(b)).unwrap())?;
+            let worst = of_color
+                .iter()
+                .max_by(|a, b| mean(a).partial_cmp(&mean(b)).unwrap())?;
+            let marginals = of_color
+                .iter()
+                .map(|x| x.marginal)
+                .collect::<ParallelVariance>();
+            Some(ColorFairness {
+                color,
+                best: best.city,
+                worst: worst.city,
+                spread: mean(&worst) - mean(&best),
+                marginal_stdev: marginals.stdev_pop(),
+            })
+        })
+        .collect();
+
+    Fairness {
+        overall,
+        cheap_cutoff,
+        cities,
+        colors,
+    }
+}
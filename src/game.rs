@@ -0,0 +1,322 @@
+use crate::data::{BoardGraph, Cost, Position};
+use crate::graph::{self, UnGraph};
+use crate::map::Hand;
+use fnv::{FnvBuildHasher, FnvHashSet};
+
+pub type Network = UnGraph<Position, (), (), FnvBuildHasher>;
+
+/// Rail units a player may place each turn; a `Cost::Two` segment uses both.
+pub const RAILS_PER_TURN: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    NotYourTurn,
+    NotOnBoard,
+    StartAlreadyPlaced,
+    NoStart,
+    NotAnEdge,
+    AlreadyBuilt,
+    NotConnected,
+    OverBudget,
+    RoundOver,
+}
+
+/// One round of Transamerica: everyone builds on a single shared network,
+/// and the round ends as soon as someone links all of their cities.
+///
+/// Since the track is shared, a turn can complete another player's network
+/// too.  If several players are linked at once, the one whose turn it was
+/// wins, and otherwise the first of the others in turn order.
+#[derive(Clone)]
+pub struct Game<'a> {
+    board: &'a BoardGraph,
+    hands: Vec<Hand>,
+    starts: Vec<Option<Position>>,
+    network: Network,
+    turn: usize,
+    winner: Option<usize>,
+}
+
+impl<'a> Game<'a> {
+    pub fn new(board: &'a BoardGraph, hands: Vec<Hand>) -> Self {
+        let players = hands.len();
+        assert!(players >= 2, "need at least two players");
+        Self {
+            board,
+            hands,
+            starts: vec![None; players],
+            network: Network::with_capacity(board.nodes().len(), board.edges().len()),
+            turn: 0,
+            winner: None,
+        }
+    }
+
+    pub fn board(&self) -> &'a BoardGraph {
+        self.board
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.hands.len()
+    }
+
+    pub fn hand(&self, player: usize) -> &[Position] {
+        &self.hands[player]
+    }
+
+    pub fn start(&self, player: usize) -> Option<Position> {
+        self.starts[player]
+    }
+
+    /// The track built so far, shared by every player.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Whose turn it is, counting from zero and wrapping around the table.
+    pub fn current_player(&self) -> usize {
+        self.turn % self.player_count()
+    }
+
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner.is_some()
+    }
+
+    /// Starting markers go down in turn order before any track is built.
+    pub fn place_start(&mut self, player: usize, pos: Position) -> Result<(), MoveError> {
+        if self.starts[player].is_some() {
+            return Err(MoveError::StartAlreadyPlaced);
+        }
+        if self.starts.iter().position(Option::is_none) != Some(player) {
+            return Err(MoveError::NotYourTurn);
+        }
+        if !self.board.contains_node(pos) {
+            return Err(MoveError::NotOnBoard);
+        }
+        self.starts[player] = Some(pos);
+        Ok(())
+    }
+
+    /// Every node `player` may currently build from: their starting marker
+    /// and whatever track is connected to it.
+    pub fn reachable(&self, player: usize) -> FnvHashSet<Position> {
+        let mut seen = FnvHashSet::default();
+        let start = match self.starts[player] {
+            Some(s) => s,
+            None => return seen,
+        };
+        let mut stack = vec![start];
+        seen.insert(start);
+        while let Some(n) = stack.pop() {
+            if self.network.contains_node(n) {
+                for (m, _) in self.network.neighbours(n) {
+                    if seen.insert(m) {
+                        stack.push(m);
+                    }
+                }
+            }
+        }
+        seen
+    }
+
+    pub fn is_built(&self, a: Position, b: Position) -> bool {
+        self.network.contains_edge(a, b)
+    }
+
+    /// Checks a single segment for `player` without building it, returning
+    /// the rail units it would use.
+    pub fn check_segment(
+        &self,
+        player: usize,
+        a: Position,
+        b: Position,
+    ) -> Result<usize, MoveError> {
+        if self.starts[player].is_none() {
+            return Err(MoveError::NoStart);
+        }
+        if a == b || !self.board.contains_node(a) || !self.board.contains_node(b) {
+            return Err(MoveError::NotAnEdge);
+        }
+        let rails = self
+            .board
+            .get_edge(a, b)
+            .and_then(|e| e.cost.rails())
+            .ok_or(MoveError::NotAnEdge)?;
+        if self.is_built(a, b) {
+            return Err(MoveError::AlreadyBuilt);
+        }
+        let reachable = self.reachable(player);
+        if !reachable.contains(&a) && !reachable.contains(&b) {
+            return Err(MoveError::NotConnected);
+        }
+        Ok(rails)
+    }
+
+    /// The game as it would stand partway through `player`'s turn, after
+    /// building `segments`, along with the rail units they use.
+    pub fn preview_turn(
+        &self,
+        player: usize,
+        segments: &[(Position, Position)],
+    ) -> Result<(Self, usize), MoveError> {
+        if self.is_over() {
+            return Err(MoveError::RoundOver);
+        }
+        if player != self.current_player() || self.starts.iter().any(Option::is_none) {
+            return Err(MoveError::NotYourTurn);
+        }
+
+        let mut trial = self.clone();
+        let mut used = 0;
+        for &(a, b) in segments {
+            used += trial.check_segment(player, a, b)?;
+            if used > RAILS_PER_TURN {
+                return Err(MoveError::OverBudget);
+            }
+            trial.build(a, b);
+        }
+        Ok((trial, used))
+    }
+
+    /// Checks a whole turn for `player` without building it, returning the
+    /// rail units it would use.
+    pub fn check_turn(
+        &self,
+        player: usize,
+        segments: &[(Position, Position)],
+    ) -> Result<usize, MoveError> {
+        self.preview_turn(player, segments).map(|x| x.1)
+    }
+
+    /// Builds the given segments, in order, as the current player's turn.
+    ///
+    /// Later segments may extend earlier ones.  Nothing is built unless the
+    /// whole turn is legal.  Returns the winner if this turn ended the round,
+    /// who need not be `player`.
+    pub fn play_turn(
+        &mut self,
+        player: usize,
+        segments: &[(Position, Position)],
+    ) -> Result<Option<usize>, MoveError> {
+        self.check_turn(player, segments)?;
+        segments.iter().for_each(|&(a, b)| self.build(a, b));
+
+        self.turn += 1;
+        let players = self.player_count();
+        self.winner = (0..players)
+            .map(|i| (player + i) % players)
+            .find(|&p| self.is_connected(p));
+        Ok(self.winner)
+    }
+
+    fn build(&mut self, a: Position, b: Position) {
+        self.network.try_add_node(a, ());
+        self.network.try_add_node(b, ());
+        self.network.add_edge(a, b, ());
+    }
+
+    /// True if all of `player`'s cities are on track linked to their marker.
+    pub fn is_connected(&self, player: usize) -> bool {
+        let reachable = self.reachable(player);
+        self.hands[player].iter().all(|c| reachable.contains(c))
+    }
+
+    /// The board with already-built track free, for planning the rest.
+    pub fn remaining_board(&self) -> BoardGraph {
+        let mut board = self.board.clone();
+        board.edges_mut().for_each(|(i, j, e)| {
+            if self.is_built(i, j) {
+                e.cost = Cost::Zero;
+            }
+        });
+        board
+    }
+
+    /// Rail units `player` still needs to link their cities, which is what
+    /// they lose when the round ends.  Exact, using the shared track for free.
+    /// `None` if the board can't link them at all.
+    pub fn remaining_cost(&self, player: usize) -> Option<usize> {
+        let start = match self.starts[player] {
+            Some(s) => s,
+            None => return Some(0),
+        };
+        let mut terminals = vec![start];
+        terminals.extend(self.hands[player].iter().cloned().filter(|&c| c != start));
+        let board = self.remaining_board();
+        let tree = graph::steiner_tree_exact_usize(&board, &terminals, |e| e.cost.rails());
+        tree.ok().map(|x| x.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{make_board, Position as P};
+
+    #[test]
+    fn turn_rules() {
+        let board = make_board();
+        let hands = vec![
+            vec![P(0, 2), P(2, 3), P(4, 4), P(6, 11), P(11, 0)],
+            vec![P(0, 3), P(3, 1), P(4, 8), P(10, 11), P(13, 2)],
+        ];
+        let mut game = Game::new(&board, hands);
+
+        assert_eq!(game.place_start(1, P(5, 5)), Err(MoveError::NotYourTurn));
+        game.place_start(0, P(5, 5)).unwrap();
+        game.place_start(1, P(8, 8)).unwrap();
+
+        assert_eq!(game.play_turn(1, &[]), Err(MoveError::NotYourTurn));
+        assert_eq!(
+            game.play_turn(0, &[(P(8, 8), P(9, 8))]),
+            Err(MoveError::NotConnected)
+        );
+        assert_eq!(
+            game.play_turn(
+                0,
+                &[(P(5, 5), P(6, 5)), (P(6, 5), P(7, 5)), (P(7, 5), P(8, 5))]
+            ),
+            Err(MoveError::OverBudget)
+        );
+        assert_eq!(game.network().edges().len(), 0);
+
+        game.play_turn(0, &[(P(5, 5), P(6, 5)), (P(6, 5), P(7, 5))])
+            .unwrap();
+        assert_eq!(game.current_player(), 1);
+        assert!(game.reachable(0).contains(&P(7, 5)));
+        assert!(!game.reachable(1).contains(&P(7, 5)));
+        let terminals = [P(5, 5), P(0, 2), P(2, 3), P(4, 4), P(6, 11), P(11, 0)];
+        let (from_scratch, _) =
+            graph::steiner_tree_exact_usize(&board, &terminals, |e| e.cost.rails()).unwrap();
+        assert!(game.remaining_cost(0).unwrap() < from_scratch);
+    }
+
+    #[test]
+    fn shared_track_can_win_for_someone_else() {
+        let board = make_board();
+        let far = vec![P(0, 2), P(2, 3), P(4, 4), P(6, 11), P(11, 0)];
+        let segments = [(P(5, 5), P(6, 5)), (P(6, 5), P(7, 5))];
+
+        // Player 0's track reaches player 1's marker and links their cities
+        let mut game = Game::new(&board, vec![far.clone(), vec![P(5, 5), P(6, 5)]]);
+        game.place_start(0, P(5, 5)).unwrap();
+        game.place_start(1, P(7, 5)).unwrap();
+        assert_eq!(game.play_turn(0, &segments), Ok(Some(1)));
+        assert!(game.is_over() && !game.is_connected(0));
+
+        // When both are linked at once the player whose turn it was wins
+        let mut game = Game::new(&board, vec![vec![P(6, 5)], vec![P(5, 5)], far]);
+        game.place_start(0, P(5, 5)).unwrap();
+        game.place_start(1, P(7, 5)).unwrap();
+        game.place_start(2, P(0, 2)).unwrap();
+        assert_eq!(game.play_turn(0, &segments), Ok(Some(0)));
+        assert!(game.is_connected(1));
+    }
+}
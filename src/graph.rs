@@ -1,11 +1,14 @@
-use std::cmp::Reverse;
 use std::collections::hash_map::{Entry, HashMap, RandomState};
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
 use std::hash::{BuildHasher, Hash};
-use std::ops::{Add, AddAssign};
 
 use crate::bucket_queue::BucketQueue;
 use crate::union_find::SimpleDisjointSet;
+use crate::weight::{PriorityQueue, Weight};
+
+pub mod mst;
 
 pub struct UnGraph<I, N, E, S = RandomState> {
     nodes: HashMap<I, N, S>,
@@ -52,6 +55,10 @@ impl<I: Copy + Ord + Hash, N, E, S: BuildHasher + Default> UnGraph<I, N, E, S> {
         self.nodes.iter_mut().map(|(k, v)| (*k, v))
     }
 
+    pub fn get_node(&self, i: I) -> Option<&N> {
+        self.nodes.get(&i)
+    }
+
     pub fn contains_node(&self, i: I) -> bool {
         self.nodes.contains_key(&i)
     }
@@ -62,6 +69,8 @@ impl<I: Copy + Ord + Hash, N, E, S: BuildHasher + Default> UnGraph<I, N, E, S> {
 
     pub fn try_add_node(&mut self, i: I, n: N) -> Option<&mut N> {
         if let Entry::Vacant(v) = self.nodes.entry(i) {
+            // Even a node without edges has neighbours, just none of them
+            self.adjacency.insert(i, HashSet::default());
             Some(v.insert(n))
         } else {
             None
@@ -136,19 +145,36 @@ impl<I: Copy + Ord + Hash, N, E, S: BuildHasher + Default> UnGraph<I, N, E, S> {
     }
 }
 
-pub fn kruskal_mst_weight_usize<I, S>(
-    metric_closure: &UnGraph<I, (), usize, S>,
+/// Two nodes with no path between them, where one was needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotConnected<I>(pub I, pub I);
+
+impl<I: fmt::Debug> fmt::Display for NotConnected<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} and {:?} are not connected", self.0, self.1)
+    }
+}
+
+impl<I: fmt::Debug> std::error::Error for NotConnected<I> {}
+
+/// The weight of a minimum spanning tree over `nodes` of a metric closure,
+/// or the first pair the closure has no edge between.
+///
+/// `mst::kruskal_complete` gives the edges too.
+pub fn kruskal_mst_weight<I, C, S>(
+    metric_closure: &UnGraph<I, (), C, S>,
     nodes: &[I],
-) -> usize
+) -> Result<C, NotConnected<I>>
 where
     I: Copy + Ord + Hash,
+    C: Weight,
     S: BuildHasher + Default,
 {
-    let mut queue = Vec::with_capacity(nodes.len() * (nodes.len() - 1) / 2);
+    let mut queue = Vec::with_capacity(nodes.len() * nodes.len().saturating_sub(1) / 2);
     for (i, &n) in nodes.iter().enumerate() {
         for (j, &m) in nodes.iter().enumerate() {
             if i > j {
-                let d = *metric_closure.get_edge(n, m).unwrap();
+                let d = *metric_closure.get_edge(n, m).ok_or(NotConnected(m, n))?;
                 queue.push((d, (i, j)));
             }
         }
@@ -156,7 +182,7 @@ where
     queue.sort_by_key(|x| x.0);
     let mut queue = queue.into_iter();
 
-    let mut mst_len = 0;
+    let mut mst_len = C::default();
 
     let mut forest = SimpleDisjointSet::new(nodes.len());
     while forest.set_count() > 1 {
@@ -166,21 +192,339 @@ where
         }
     }
 
-    mst_len
+    Ok(mst_len)
 }
 
-pub fn metric_closure_usize<I, N, E, S>(
+pub fn kruskal_mst_weight_usize<I, S>(
+    metric_closure: &UnGraph<I, (), usize, S>,
+    nodes: &[I],
+) -> Result<usize, NotConnected<I>>
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+{
+    kruskal_mst_weight(metric_closure, nodes)
+}
+
+/// A minimum spanning tree over some nodes of a `Metric`, kept so that
+/// the weight with one more node costs time linear in the nodes, instead of
+/// the `kruskal_mst_weight_usize` from scratch.
+///
+/// This is Chin and Houck's vertex insertion: the new tree only needs the
+/// old tree's edges and the new node's, and one pass up from the leaves
+/// drops the heaviest edge of every cycle through the new node.
+#[derive(Debug, Clone)]
+pub struct IncrementalMst<I> {
+    /// Every node's parent comes before it; the first is the root.
+    nodes: Vec<I>,
+    /// The index of each node's parent and the cost of the edge to it, with
+    /// `(0, 0)` for the root.
+    parents: Vec<(usize, usize)>,
+    weight: usize,
+}
+
+impl<I: Copy + Eq> IncrementalMst<I> {
+    /// Prim's algorithm over `nodes`, which must all be distinct, or the
+    /// first node found that isn't connected to the first.
+    pub fn new(metric: &impl Metric<I>, nodes: &[I]) -> Result<Self, NotConnected<I>> {
+        let mut mst = Self {
+            nodes: Vec::with_capacity(nodes.len() + 1),
+            parents: Vec::with_capacity(nodes.len() + 1),
+            weight: 0,
+        };
+        // The cheapest edge from each node into the tree so far
+        let mut best = vec![None; nodes.len()];
+        let mut added = vec![false; nodes.len()];
+        let mut next = 0;
+        while next < nodes.len() {
+            added[next] = true;
+            let (cost, parent) = best[next].unwrap_or((0, 0));
+            mst.weight += cost;
+            mst.nodes.push(nodes[next]);
+            mst.parents.push((parent, cost));
+
+            let here = mst.nodes.len() - 1;
+            for (j, &m) in nodes.iter().enumerate() {
+                if let (false, Some(d)) = (added[j], metric.distance(nodes[next], m)) {
+                    if best[j].is_none_or(|(c, _)| d < c) {
+                        best[j] = Some((d, here));
+                    }
+                }
+            }
+            let rest = (0..nodes.len()).filter(|&j| !added[j]);
+            if let Some(j) = rest.clone().find(|&j| best[j].is_none()) {
+                return Err(NotConnected(nodes[0], nodes[j]));
+            }
+            next = rest
+                .min_by_key(|&j| best[j].unwrap().0)
+                .unwrap_or(nodes.len());
+        }
+        Ok(mst)
+    }
+
+    pub fn nodes(&self) -> &[I] {
+        &self.nodes
+    }
+
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Each node but the root with its parent and the cost between them.
+    pub fn edges(&self) -> impl Iterator<Item = (I, I, usize)> + '_ {
+        self.nodes
+            .iter()
+            .zip(&self.parents)
+            .skip(1)
+            .map(move |(&n, &(parent, cost))| (n, self.nodes[parent], cost))
+    }
+
+    /// The weight of the tree if `n`, which must not be in it yet, were
+    /// added, or `None` if `n` isn't connected to it.
+    pub fn weight_with(&self, metric: &impl Metric<I>, n: I) -> Option<usize> {
+        if self.nodes.is_empty() {
+            return Some(0);
+        }
+        // The cheapest way found so far to join each subtree to `n`
+        let mut pending = self
+            .nodes
+            .iter()
+            .map(|&m| metric.distance(n, m))
+            .collect::<Option<Vec<_>>>()?;
+        let mut weight = 0;
+        for i in (1..self.nodes.len()).rev() {
+            let (parent, cost) = self.parents[i];
+            weight += pending[i].min(cost);
+            pending[parent] = pending[parent].min(pending[i].max(cost));
+        }
+        Some(weight + pending[0])
+    }
+
+    /// Adds `n`, which must not be in the tree yet, like `weight_with`.
+    ///
+    /// Panics if `n` isn't connected to the tree, which `weight_with` tells.
+    pub fn push(&mut self, metric: &impl Metric<I>, n: I) {
+        let len = self.nodes.len();
+        if len == 0 {
+            self.nodes.push(n);
+            self.parents.push((0, 0));
+            return;
+        }
+
+        // As in `weight_with`, but remembering the edges: `(cost, (i, j))`
+        // with `len` standing for `n`
+        let mut pending = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &m)| {
+                let d = metric.distance(n, m).expect("not connected to the tree");
+                (d, (i, len))
+            })
+            .collect::<Vec<_>>();
+        let mut adjacency = vec![Vec::new(); len + 1];
+        let mut keep = |(cost, (i, j)): (usize, (usize, usize))| {
+            adjacency[i].push((j, cost));
+            adjacency[j].push((i, cost));
+        };
+        for i in (1..len).rev() {
+            let (parent, cost) = self.parents[i];
+            let tree = (cost, (i, parent));
+            let (light, heavy) = if pending[i].0 <= cost {
+                (pending[i], tree)
+            } else {
+                (tree, pending[i])
+            };
+            keep(light);
+            if heavy.0 < pending[parent].0 {
+                pending[parent] = heavy;
+            }
+        }
+        keep(pending[0]);
+
+        // Re-root at the old root, parents first
+        let old = std::mem::take(&mut self.nodes);
+        self.parents.clear();
+        self.weight = 0;
+        let mut position = vec![usize::MAX; len + 1];
+        position[0] = 0;
+        self.nodes.push(old[0]);
+        self.parents.push((0, 0));
+        let mut i = 0;
+        let mut order = vec![0];
+        while i < order.len() {
+            let here = order[i];
+            for &(j, cost) in &adjacency[here] {
+                if position[j] == usize::MAX {
+                    position[j] = order.len();
+                    order.push(j);
+                    self.nodes.push(if j == len { n } else { old[j] });
+                    self.parents.push((i, cost));
+                    self.weight += cost;
+                }
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Shortest distances between nodes, like a metric closure holds.
+pub trait Metric<I> {
+    /// `None` if there's no path from `a` to `b`.
+    fn distance(&self, a: I, b: I) -> Option<usize>;
+}
+
+impl<I, S> Metric<I> for UnGraph<I, (), usize, S>
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+{
+    fn distance(&self, a: I, b: I) -> Option<usize> {
+        if a == b {
+            Some(0)
+        } else {
+            self.get_edge(a, b).cloned()
+        }
+    }
+}
+
+/// A metric closure as a flat table, with the nodes numbered `0..len()` in
+/// order, so that looking up a distance is indexing instead of hashing.
+#[derive(Debug, Clone)]
+pub struct DistanceMatrix<I, S = RandomState> {
+    ids: Vec<I>,
+    indices: HashMap<I, u32, S>,
+    /// Row-major, with `NO_PATH` between nodes that aren't connected.
+    distances: Vec<u16>,
+}
+
+const NO_PATH: u16 = u16::MAX;
+
+/// The largest distance a `DistanceMatrix` can store.
+pub const MAX_DISTANCE: usize = NO_PATH as usize - 1;
+
+/// Two nodes further apart than a `DistanceMatrix` can store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooFar<I>(pub I, pub I, pub usize);
+
+impl<I: fmt::Debug> fmt::Display for TooFar<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} and {:?} are {} apart, more than {} can be stored",
+            self.0, self.1, self.2, MAX_DISTANCE
+        )
+    }
+}
+
+impl<I: fmt::Debug> std::error::Error for TooFar<I> {}
+
+impl<I: Copy + Ord + Hash, S: BuildHasher + Default> DistanceMatrix<I, S> {
+    /// Fails if a distance doesn't fit in a `u16`.
+    pub fn from_metric_closure<N>(closure: &UnGraph<I, N, usize, S>) -> Result<Self, TooFar<I>> {
+        let mut ids = closure.node_ids().collect::<Vec<_>>();
+        ids.sort();
+        let indices = ids
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| (i, n as u32))
+            .collect::<HashMap<_, _, S>>();
+        let len = ids.len();
+        let mut distances = vec![NO_PATH; len * len];
+        for n in 0..len {
+            distances[n * len + n] = 0;
+        }
+        for (i, j, &d) in closure.edges() {
+            let d = u16::try_from(d)
+                .ok()
+                .filter(|&d| d != NO_PATH)
+                .ok_or(TooFar(i, j, d))?;
+            let (a, b) = (indices[&i] as usize, indices[&j] as usize);
+            distances[a * len + b] = d;
+            distances[b * len + a] = d;
+        }
+        Ok(Self {
+            ids,
+            indices,
+            distances,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn index(&self, i: I) -> Option<u32> {
+        self.indices.get(&i).cloned()
+    }
+
+    pub fn id(&self, n: u32) -> I {
+        self.ids[n as usize]
+    }
+
+    pub fn ids(&self) -> &[I] {
+        &self.ids
+    }
+
+    /// The distances, row by row, with `u16::MAX` for no path.
+    pub fn table(&self) -> &[u16] {
+        &self.distances
+    }
+
+    /// The inverse of `ids` and `table`, if they fit together.
+    pub fn from_table(ids: Vec<I>, distances: Vec<u16>) -> Option<Self> {
+        let sorted = ids.windows(2).all(|x| x[0] < x[1]);
+        if !sorted || distances.len() != ids.len() * ids.len() {
+            return None;
+        }
+        let indices = ids
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| (i, n as u32))
+            .collect();
+        Some(Self {
+            ids,
+            indices,
+            distances,
+        })
+    }
+
+    pub fn get(&self, a: u32, b: u32) -> Option<usize> {
+        match self.distances[a as usize * self.ids.len() + b as usize] {
+            NO_PATH => None,
+            d => Some(usize::from(d)),
+        }
+    }
+}
+
+impl<I, S> Metric<u32> for DistanceMatrix<I, S>
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+{
+    fn distance(&self, a: u32, b: u32) -> Option<usize> {
+        self.get(a, b)
+    }
+}
+
+/// Shortest distances between every pair of nodes that are connected.
+pub fn metric_closure<I, N, E, S, C>(
     g: &UnGraph<I, N, E, S>,
-    cost: impl Fn(&E) -> usize,
-) -> UnGraph<I, (), usize, S>
+    cost: impl Fn(&E) -> Option<C>,
+) -> UnGraph<I, (), C, S>
 where
     I: Copy + Ord + Hash,
     S: Default + BuildHasher,
+    C: Weight,
 {
     let mut closure = UnGraph::with_capacity(g.nodes().len(), g.nodes().len());
     g.node_ids().for_each(|n| closure.add_node(n, ()));
     g.node_ids().for_each(|n| {
-        let distances = dijkstra_usize(g, n, &cost);
+        let distances = dijkstra(g, n, &cost);
         distances.nodes().for_each(|(m, d)| {
             if n != m {
                 closure.try_add_edge(n, m, *d);
@@ -190,24 +534,39 @@ where
     closure
 }
 
-pub fn dijkstra_usize<I, N, E, S>(
+pub fn metric_closure_usize<I, N, E, S>(
+    g: &UnGraph<I, N, E, S>,
+    cost: impl Fn(&E) -> Option<usize>,
+) -> UnGraph<I, (), usize, S>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+{
+    metric_closure(g, cost)
+}
+
+/// Distances from `seed` to every node it can reach, as node weights.
+///
+/// Edges `cost` gives `None` are impassable; they're left out.
+pub fn dijkstra<I, N, E, S, C>(
     g: &UnGraph<I, N, E, S>,
     seed: I,
-    cost: impl Fn(&E) -> usize,
-) -> UnGraph<I, usize, (), S>
+    cost: impl Fn(&E) -> Option<C>,
+) -> UnGraph<I, C, (), S>
 where
     I: Copy + Ord + Hash,
     S: Default + BuildHasher,
+    C: Weight,
 {
     let mut distances = UnGraph::with_capacity(g.nodes().len(), 0);
 
-    let mut queue = BucketQueue::default();
-    queue.push(0, seed);
+    let mut queue = C::Queue::default();
+    queue.push(C::default(), seed);
     while let Some((d, n)) = queue.pop() {
         if distances.try_add_node(n, d).is_some() {
             for (m, e) in g.neighbours(n) {
-                if !distances.contains_node(m) {
-                    queue.push(d + cost(e), m);
+                if let (false, Some(c)) = (distances.contains_node(m), cost(e)) {
+                    queue.push(d + c, m);
                 }
             }
         }
@@ -216,35 +575,131 @@ where
     distances
 }
 
+pub fn dijkstra_usize<I, N, E, S>(
+    g: &UnGraph<I, N, E, S>,
+    seed: I,
+    cost: impl Fn(&E) -> Option<usize>,
+) -> UnGraph<I, usize, (), S>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+{
+    dijkstra(g, seed, cost)
+}
+
+/// Distances from a seed, plus the shortest-path tree that achieves them.
+pub struct ShortestPaths<I, S = RandomState, C = usize> {
+    seed: I,
+    tree: UnGraph<I, C, (), S>,
+    predecessors: HashMap<I, I, S>,
+}
+
+impl<I: Copy + Ord + Hash, S: BuildHasher + Default, C: Weight> ShortestPaths<I, S, C> {
+    pub fn seed(&self) -> I {
+        self.seed
+    }
+
+    /// Node weights are distances from the seed; edges form the tree.
+    pub fn tree(&self) -> &UnGraph<I, C, (), S> {
+        &self.tree
+    }
+
+    pub fn distance(&self, target: I) -> Option<C> {
+        self.tree.get_node(target).cloned()
+    }
+
+    /// The nodes along a shortest path, starting at the seed and ending at `target`.
+    pub fn path_to(&self, target: I) -> Option<Vec<I>> {
+        if !self.tree.contains_node(target) {
+            return None;
+        }
+
+        let mut path = vec![target];
+        let mut n = target;
+        while let Some(&p) = self.predecessors.get(&n) {
+            path.push(p);
+            n = p;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+pub fn dijkstra_paths<I, N, E, S, C>(
+    g: &UnGraph<I, N, E, S>,
+    seed: I,
+    cost: impl Fn(&E) -> Option<C>,
+) -> ShortestPaths<I, S, C>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+    C: Weight,
+{
+    let mut tree = UnGraph::with_capacity(g.nodes().len(), g.nodes().len());
+    let mut predecessors = HashMap::with_capacity_and_hasher(g.nodes().len(), S::default());
+
+    let mut queue = C::Queue::default();
+    queue.push(C::default(), (seed, None));
+    while let Some((d, (n, p))) = queue.pop() {
+        if tree.try_add_node(n, d).is_some() {
+            if let Some(p) = p {
+                tree.add_edge(p, n, ());
+                predecessors.insert(n, p);
+            }
+            for (m, e) in g.neighbours(n) {
+                if let (false, Some(c)) = (tree.contains_node(m), cost(e)) {
+                    queue.push(d + c, (m, Some(n)));
+                }
+            }
+        }
+    }
+
+    ShortestPaths {
+        seed,
+        tree,
+        predecessors,
+    }
+}
+
+pub fn dijkstra_paths_usize<I, N, E, S>(
+    g: &UnGraph<I, N, E, S>,
+    seed: I,
+    cost: impl Fn(&E) -> Option<usize>,
+) -> ShortestPaths<I, S>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+{
+    dijkstra_paths(g, seed, cost)
+}
+
 pub fn steiner_mst<I, N, E, S, C>(
     g: &UnGraph<I, N, E, S>,
     seed: I,
     terminals: impl Iterator<Item = I>,
-    cost: impl Fn(&E) -> C,
+    cost: impl Fn(&E) -> Option<C>,
 ) -> (C, UnGraph<I, (), (), S>)
 where
     I: Copy + Ord + Hash,
     S: Default + BuildHasher,
-    C: Copy + Ord + Default + Add<Output = C> + AddAssign,
+    C: Weight,
 {
     let mut tree_cost = C::default();
     let mut tree = UnGraph::with_capacity(1, 0);
     tree.add_node(seed, ());
 
     let mut terminals = terminals.zip(0..).collect::<HashMap<_, usize, S>>();
-    let mut heap = BinaryHeap::new();
+    let mut heap = C::Queue::default();
     let mut incoming = HashMap::with_hasher(S::default());
     while !terminals.is_empty() {
         incoming.clear();
         heap.clear();
-        heap.extend(
-            terminals
-                .iter()
-                .map(|t| Reverse((C::default(), *t.0, Err(*t.1)))),
-        );
+        for (&t, &i) in &terminals {
+            heap.push(C::default(), (t, Err(i)));
+        }
 
         let (mut prev, path_cost) = loop {
-            let Reverse((c, n, p)) = heap.pop().expect("tree not reachable from terminals");
+            let (c, (n, p)) = heap.pop().expect("tree not reachable from terminals");
             if let Entry::Vacant(entry) = incoming.entry(n) {
                 entry.insert(p);
 
@@ -253,8 +708,8 @@ where
                 }
 
                 for (m, e) in g.neighbours(n) {
-                    if !incoming.contains_key(&m) {
-                        heap.push(Reverse((c + cost(e), m, Ok(n))));
+                    if let (false, Some(w)) = (incoming.contains_key(&m), cost(e)) {
+                        heap.push(c + w, (m, Ok(n)));
                     }
                 }
             }
@@ -277,54 +732,454 @@ pub fn steiner_mst_usize<I, N, E, S>(
     g: &UnGraph<I, N, E, S>,
     seed: I,
     terminals: impl Iterator<Item = I>,
-    cost: impl Fn(&E) -> usize,
+    cost: impl Fn(&E) -> Option<usize>,
 ) -> (usize, UnGraph<I, (), (), S>)
 where
     I: Copy + Ord + Hash,
     S: Default + BuildHasher,
 {
-    let mut tree_cost = 0;
-    let mut tree = UnGraph::with_capacity(1, 0);
-    tree.add_node(seed, ());
+    steiner_mst(g, seed, terminals, cost)
+}
 
-    let mut terminals = terminals.zip(0..).collect::<HashMap<_, usize, S>>();
-    let mut heap = BucketQueue::default();
-    let mut incoming = HashMap::with_hasher(S::default());
-    while !terminals.is_empty() {
-        incoming.clear();
-        heap.clear();
-        heap.extend(
-            terminals
-                .iter()
-                .map(|t| (0, (*t.0, Err(*t.1)))),
-        );
+#[derive(Debug, Clone, Copy)]
+enum Back {
+    Unreached,
+    Terminal,
+    Split(usize),
+    Via(usize),
+}
 
-        let (mut prev, path_cost) = loop {
-            let (c, (n, p)) = heap.pop().expect("tree not reachable from terminals");
-            if let Entry::Vacant(entry) = incoming.entry(n) {
-                entry.insert(p);
+/// The Dreyfus–Wagner table: for every subset `mask` of the terminals and
+/// every node `v`, the cheapest tree spanning that subset and `v`.
+struct SteinerTable<I> {
+    ids: Vec<I>,
+    dp: Vec<(usize, Back)>,
+}
 
-                if tree.contains_node(n) {
-                    break (n, c);
+impl<I: Copy> SteinerTable<I> {
+    fn new<N, E, S>(
+        g: &UnGraph<I, N, E, S>,
+        terminals: &[I],
+        cost: impl Fn(&E) -> Option<usize>,
+    ) -> Self
+    where
+        I: Ord + Hash,
+        S: Default + BuildHasher,
+    {
+        let ids = g.node_ids().collect::<Vec<_>>();
+        let index = ids
+            .iter()
+            .cloned()
+            .zip(0..)
+            .collect::<HashMap<_, usize, S>>();
+        let adjacency = ids
+            .iter()
+            .map(|&n| {
+                g.neighbours(n)
+                    .filter_map(|(m, e)| Some((index[&m], cost(e)?)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let n = ids.len();
+        let full = (1 << terminals.len()) - 1;
+        let mut dp = vec![(usize::MAX, Back::Unreached); (full + 1) * n];
+        let mut queue = BucketQueue::default();
+        for mask in 1..=full {
+            let row = mask * n;
+            if mask & (mask - 1) == 0 {
+                let t = index[&terminals[mask.trailing_zeros() as usize]];
+                dp[row + t] = (0, Back::Terminal);
+            } else {
+                // Only splits containing the lowest bit, so each pair is seen once
+                let low = mask & mask.wrapping_neg();
+                let mut sub = (mask - 1) & mask;
+                while sub != 0 {
+                    if sub & low != 0 {
+                        for v in 0..n {
+                            let c = dp[sub * n + v].0.saturating_add(dp[(mask ^ sub) * n + v].0);
+                            if c < dp[row + v].0 {
+                                dp[row + v] = (c, Back::Split(sub));
+                            }
+                        }
+                    }
+                    sub = (sub - 1) & mask;
                 }
+            }
 
-                for (m, e) in g.neighbours(n) {
-                    if !incoming.contains_key(&m) {
-                        heap.push(c + cost(e), (m, Ok(n)));
+            queue.clear();
+            for v in 0..n {
+                if dp[row + v].0 != usize::MAX {
+                    queue.push(dp[row + v].0, v);
+                }
+            }
+            while let Some((d, u)) = queue.pop() {
+                if d > dp[row + u].0 {
+                    continue;
+                }
+                for &(v, c) in &adjacency[u] {
+                    if d + c < dp[row + v].0 {
+                        dp[row + v] = (d + c, Back::Via(u));
+                        queue.push(d + c, v);
                     }
                 }
             }
-        };
-        tree_cost += path_cost;
+        }
 
-        while let &Ok(next) = incoming.get(&prev).unwrap() {
-            tree.add_node(next, ());
-            tree.add_edge(next, prev, ());
-            prev = next;
+        Self { ids, dp }
+    }
+
+    /// The cheapest tree's cost, or the first terminal found that isn't
+    /// connected to the first.
+    fn cost(&self, terminals: &[I]) -> Result<usize, NotConnected<I>>
+    where
+        I: Eq,
+    {
+        let n = self.ids.len();
+        let index = |t| self.ids.iter().position(|&m| m == t).unwrap();
+        let tree_cost = self.full_row()[index(terminals[0])].0;
+        if tree_cost == usize::MAX {
+            // The first terminal's own row holds its distance to every node
+            let t = terminals[1..]
+                .iter()
+                .find(|&&t| self.dp[n + index(t)].0 == usize::MAX)
+                .unwrap();
+            return Err(NotConnected(terminals[0], *t));
         }
+        Ok(tree_cost)
+    }
 
-        terminals.remove(&prev);
+    /// The full-set row: the cheapest tree spanning all terminals and each node.
+    fn full_row(&self) -> &[(usize, Back)] {
+        let n = self.ids.len();
+        &self.dp[self.dp.len() - n..]
     }
 
-    (tree_cost, tree)
+    fn build_tree<S>(&self, root: usize) -> UnGraph<I, (), (), S>
+    where
+        I: Ord + Hash,
+        S: Default + BuildHasher,
+    {
+        let n = self.ids.len();
+        let full = self.dp.len() / n - 1;
+        let mut tree = UnGraph::with_capacity(full.count_ones() as usize, 0);
+        let mut stack = vec![(full, root)];
+        while let Some((mask, v)) = stack.pop() {
+            tree.try_add_node(self.ids[v], ());
+            match self.dp[mask * n + v].1 {
+                Back::Unreached => unreachable!(),
+                Back::Terminal => {}
+                Back::Split(sub) => {
+                    stack.push((sub, v));
+                    stack.push((mask ^ sub, v));
+                }
+                Back::Via(u) => {
+                    tree.try_add_node(self.ids[u], ());
+                    tree.try_add_edge(self.ids[u], self.ids[v], ());
+                    stack.push((mask, u));
+                }
+            }
+        }
+        tree
+    }
+}
+
+/// Nodes and edges without weights, as the Steiner solvers lay out.
+pub type Tree<I, S> = UnGraph<I, (), (), S>;
+
+/// Exact minimum Steiner tree connecting `terminals`, via Dreyfus–Wagner.
+///
+/// Runs over the neighbours of `g`, so it can be given either the board itself
+/// or the output of `metric_closure_usize`; the sparse board is much faster.
+/// Exponential in the number of terminals, but five is no problem.  Fails
+/// with the first terminal found that isn't connected to the first.
+pub fn steiner_tree_exact_usize<I, N, E, S>(
+    g: &UnGraph<I, N, E, S>,
+    terminals: &[I],
+    cost: impl Fn(&E) -> Option<usize>,
+) -> Result<(usize, Tree<I, S>), NotConnected<I>>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+{
+    if terminals.len() <= 1 {
+        let mut tree = UnGraph::with_capacity(terminals.len(), 0);
+        terminals.iter().for_each(|&t| tree.add_node(t, ()));
+        return Ok((0, tree));
+    }
+
+    let table = SteinerTable::new(g, terminals, cost);
+    let root = table.ids.iter().position(|&n| n == terminals[0]).unwrap();
+    Ok((table.cost(terminals)?, table.build_tree(root)))
+}
+
+/// Cheapest trees connecting `terminals` that between them pass through
+/// every node any cheapest tree does.
+///
+/// These are the nodes where `steiner_costs_exact_usize` is lowest, and that
+/// lowest cost is what each tree costs.
+pub fn steiner_trees_exact_usize<I, N, E, S>(
+    g: &UnGraph<I, N, E, S>,
+    terminals: &[I],
+    cost: impl Fn(&E) -> Option<usize>,
+) -> Result<Vec<Tree<I, S>>, NotConnected<I>>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+{
+    if terminals.len() <= 1 {
+        let (_, tree) = steiner_tree_exact_usize(g, terminals, cost)?;
+        return Ok(vec![tree]);
+    }
+
+    let table = SteinerTable::new(g, terminals, cost);
+    let tree_cost = table.cost(terminals)?;
+    let mut covered = HashSet::<I, S>::default();
+    let mut trees = Vec::new();
+    for (v, &(c, _)) in table.full_row().iter().enumerate() {
+        if c == tree_cost && !covered.contains(&table.ids[v]) {
+            let tree: Tree<I, S> = table.build_tree(v);
+            covered.extend(tree.node_ids());
+            trees.push(tree);
+        }
+    }
+    Ok(trees)
+}
+
+/// For every node, the exact cost of the cheapest tree connecting `terminals`
+/// and that node, as node weights like `dijkstra_usize`.
+///
+/// This is the Steiner cost of every possible `seed` at once, for the price of
+/// a single `steiner_tree_exact_usize`.  Unreachable nodes are left out.
+pub fn steiner_costs_exact_usize<I, N, E, S>(
+    g: &UnGraph<I, N, E, S>,
+    terminals: &[I],
+    cost: impl Fn(&E) -> Option<usize>,
+) -> UnGraph<I, usize, (), S>
+where
+    I: Copy + Ord + Hash,
+    S: Default + BuildHasher,
+{
+    let mut costs = UnGraph::with_capacity(g.nodes().len(), 0);
+    if terminals.is_empty() {
+        g.node_ids().for_each(|n| costs.add_node(n, 0));
+        return costs;
+    }
+
+    let table = SteinerTable::new(g, terminals, cost);
+    for (&n, &(c, _)) in table.ids.iter().zip(table.full_row()) {
+        if c != usize::MAX {
+            costs.add_node(n, c);
+        }
+    }
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weight::{Fixed, TotalF64};
+    use std::collections::hash_map::RandomState;
+
+    fn star() -> UnGraph<u8, (), usize, RandomState> {
+        // Three leaves around a hub, with a costlier ring between the leaves
+        let mut g = UnGraph::with_capacity(4, 6);
+        (0..4).for_each(|n| g.add_node(n, ()));
+        g.add_edge(0, 1, 2);
+        g.add_edge(0, 2, 2);
+        g.add_edge(0, 3, 2);
+        g.add_edge(1, 2, 5);
+        g.add_edge(2, 3, 5);
+        g.add_edge(3, 1, 5);
+        g
+    }
+
+    #[test]
+    fn exact_steiner_uses_hub() {
+        let g = star();
+        let (c, tree) = steiner_tree_exact_usize(&g, &[1, 2, 3], |&e| Some(e)).unwrap();
+        assert_eq!(c, 6);
+        assert!(tree.contains_node(0));
+        assert_eq!(tree.edges().len(), 3);
+        let trees = steiner_trees_exact_usize(&g, &[1, 2, 3], |&e| Some(e)).unwrap();
+        assert_eq!(trees.len(), 1);
+        assert_eq!(trees[0].nodes().len(), 4);
+
+        let closure = metric_closure_usize(&g, |&e| Some(e));
+        assert_eq!(kruskal_mst_weight_usize(&closure, &[1, 2, 3]), Ok(8));
+        assert_eq!(
+            steiner_tree_exact_usize(&closure, &[1, 2, 3], |&e| Some(e))
+                .unwrap()
+                .0,
+            6
+        );
+    }
+
+    #[test]
+    fn incremental_mst_matches_kruskal() {
+        // Random points on a grid, at their Manhattan distances
+        let mut rng = crate::rng::Rng::new(7);
+        let points = (0..40)
+            .map(|_| (rng.below(20) as isize, rng.below(20) as isize))
+            .collect::<Vec<_>>();
+        let mut closure = UnGraph::<u8, (), usize, RandomState>::with_capacity(40, 780);
+        (0..40).for_each(|n| closure.add_node(n, ()));
+        for i in 0..40 {
+            for j in 0..i {
+                let (a, b) = (points[i as usize], points[j as usize]);
+                let d = (a.0 - b.0).abs() + (a.1 - b.1).abs();
+                closure.add_edge(i, j, d as usize);
+            }
+        }
+
+        let dense = DistanceMatrix::from_metric_closure(&closure).unwrap();
+        for _ in 0..50 {
+            let mut nodes = (0..40).collect::<Vec<u8>>();
+            rng.shuffle(&mut nodes);
+            let (start, rest) = nodes.split_at(1 + rng.below(8));
+            let mut mst = IncrementalMst::new(&closure, start).unwrap();
+            assert_eq!(Ok(mst.weight()), kruskal_mst_weight_usize(&closure, start));
+            let mut all = start.to_vec();
+            let indices = start.iter().map(|&n| u32::from(n)).collect::<Vec<_>>();
+            let mut dense_mst = IncrementalMst::new(&dense, &indices).unwrap();
+            for &n in &rest[..5] {
+                let with = mst.weight_with(&closure, n);
+                mst.push(&closure, n);
+                all.push(n);
+                assert_eq!(with, kruskal_mst_weight_usize(&closure, &all).ok());
+                assert_eq!(Some(mst.weight()), with);
+                assert_eq!(dense_mst.weight_with(&dense, u32::from(n)), with);
+                dense_mst.push(&dense, u32::from(n));
+            }
+        }
+    }
+
+    #[test]
+    fn distance_matrix_matches_closure() {
+        let mut g = star();
+        g.add_node(9, ());
+        g.add_edge(0, 9, 100);
+        let closure = metric_closure_usize(&g, |&e| if e == 100 { None } else { Some(e) });
+        let dense = DistanceMatrix::from_metric_closure(&closure).unwrap();
+        assert_eq!(dense.ids(), &[0, 1, 2, 3, 9]);
+        assert_eq!(dense.index(9), Some(4));
+        assert_eq!(dense.get(1, 2), Some(4));
+        assert_eq!(dense.get(3, 3), Some(0));
+        assert_eq!(dense.get(0, 4), None);
+        for (i, j, &d) in closure.edges() {
+            let (a, b) = (dense.index(i).unwrap(), dense.index(j).unwrap());
+            assert_eq!(dense.distance(a, b), Some(d));
+            assert_eq!(dense.distance(b, a), Some(d));
+        }
+    }
+
+    #[test]
+    fn disconnected_nodes_are_errors() {
+        let mut g = star();
+        g.add_node(9, ());
+        g.add_edge(0, 9, 100);
+        let cost = |&e: &usize| if e == 100 { None } else { Some(e) };
+        assert_eq!(
+            steiner_tree_exact_usize(&g, &[1, 2, 9, 3], cost).err(),
+            Some(NotConnected(1, 9))
+        );
+
+        let closure = metric_closure_usize(&g, cost);
+        assert_eq!(
+            kruskal_mst_weight_usize(&closure, &[2, 9]),
+            Err(NotConnected(2, 9))
+        );
+        let dense = DistanceMatrix::from_metric_closure(&closure).unwrap();
+        assert_eq!(
+            IncrementalMst::new(&dense, &[1, 2, 4]).err(),
+            Some(NotConnected(1, 4))
+        );
+        let mst = IncrementalMst::new(&dense, &[1, 2]).unwrap();
+        assert_eq!(mst.weight_with(&dense, 4), None);
+        assert_eq!(mst.weight_with(&dense, 3), Some(8));
+
+        let far = metric_closure_usize(&g, |&e| Some(e * 1000));
+        assert!(DistanceMatrix::from_metric_closure(&far).is_err());
+    }
+
+    #[test]
+    fn fractional_weights() {
+        // Spokes of 1.5 make a ring edge of 2.5 the shorter way round
+        let g = star();
+        let as_f64 = |&e: &usize| if e == 2 { 1.5 } else { e as f64 / 2.0 };
+        let fixed = |e: &usize| Fixed::from_f64(as_f64(e));
+        let total = |e: &usize| TotalF64::new(as_f64(e));
+
+        let paths = dijkstra_paths(&g, 1, fixed);
+        assert_eq!(paths.distance(2), Some(Fixed(2500)));
+        assert_eq!(paths.path_to(2), Some(vec![1, 2]));
+        assert_eq!(
+            dijkstra(&g, 1, total).get_node(0),
+            TotalF64::new(1.5).as_ref()
+        );
+
+        let closure = metric_closure(&g, total);
+        assert_eq!(
+            kruskal_mst_weight(&closure, &[1, 2, 3]),
+            Ok(TotalF64::new(5.0).unwrap())
+        );
+        assert_eq!(
+            kruskal_mst_weight(&closure, &[0, 1, 2, 3]),
+            Ok(TotalF64::new(4.5).unwrap())
+        );
+        let (c, tree) = steiner_mst(&g, 0, [1, 2, 3].iter().cloned(), fixed);
+        assert_eq!((c, tree.edges().len()), (Fixed(4500), 3));
+        for &bad in &[-1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(TotalF64::new(bad), None);
+        }
+
+        // The integer weights all agree with the usize fast path
+        let closure = metric_closure_usize(&g, |&e| Some(e));
+        let wide = metric_closure(&g, |&e| Some(e as u64));
+        for (i, j, &d) in closure.edges() {
+            assert_eq!(*wide.get_edge(i, j).unwrap(), d as u64);
+        }
+    }
+
+    #[test]
+    fn dijkstra_path_avoids_ring() {
+        let g = star();
+        let paths = dijkstra_paths_usize(&g, 1, |&e| Some(e));
+        assert_eq!(paths.distance(2), Some(4));
+        assert_eq!(paths.path_to(2), Some(vec![1, 0, 2]));
+        assert_eq!(paths.path_to(1), Some(vec![1]));
+        assert_eq!(paths.tree().edges().len(), 3);
+    }
+
+    #[test]
+    fn impassable_edges_are_skipped() {
+        let g = star();
+        let ring_only = |&e: &usize| if e == 2 { None } else { Some(e) };
+        let paths = dijkstra_paths_usize(&g, 1, ring_only);
+        assert_eq!(paths.path_to(2), Some(vec![1, 2]));
+        assert_eq!(paths.distance(0), None);
+        assert_eq!(
+            steiner_tree_exact_usize(&g, &[1, 2, 3], ring_only).map(|x| x.0),
+            Ok(10)
+        );
+    }
+
+    #[test]
+    fn exact_steiner_costs_per_seed() {
+        let g = star();
+        let costs = steiner_costs_exact_usize(&g, &[1, 2], |&e| Some(e));
+        assert_eq!(costs.get_node(0), Some(&4));
+        assert_eq!(costs.get_node(1), Some(&4));
+        assert_eq!(costs.get_node(3), Some(&6));
+    }
+
+    #[test]
+    fn exact_steiner_trivial() {
+        let g = star();
+        let cost =
+            |terminals: &[u8]| steiner_tree_exact_usize(&g, terminals, |&e| Some(e)).map(|x| x.0);
+        assert_eq!(cost(&[]), Ok(0));
+        assert_eq!(cost(&[2]), Ok(0));
+        assert_eq!(cost(&[1, 2]), Ok(4));
+    }
 }
@@ -0,0 +1,267 @@
+//! Minimum spanning trees, or forests when the graph isn't connected.
+
+use super::UnGraph;
+use crate::union_find::SimpleDisjointSet;
+use crate::weight::{PriorityQueue, Weight};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+/// A minimum spanning forest: one tree per connected component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forest<I, C> {
+    /// `(a, b, cost)`, in the order the algorithm chose them.
+    pub edges: Vec<(I, I, C)>,
+    pub weight: C,
+    /// Number of trees; isolated nodes count as one each.
+    pub components: usize,
+}
+
+impl<I, C> Forest<I, C> {
+    /// Whether everything was connected (an empty graph counts).
+    pub fn is_tree(&self) -> bool {
+        self.components <= 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MstError<I> {
+    /// A node asked for isn't in the graph.
+    MissingNode(I),
+    /// Two nodes have no edge between them in what should be a metric
+    /// closure, so they aren't connected.
+    MissingEdge(I, I),
+}
+
+impl<I: fmt::Debug> fmt::Display for MstError<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MstError::MissingNode(n) => write!(f, "{:?} is not in the graph", n),
+            MstError::MissingEdge(a, b) => write!(f, "no edge between {:?} and {:?}", a, b),
+        }
+    }
+}
+
+impl<I: fmt::Debug> std::error::Error for MstError<I> {}
+
+/// Kruskal's algorithm over every edge of `g` that `cost` doesn't rule out.
+///
+/// Ties are broken by the edges' ends, so the result doesn't depend on the
+/// graph's hashing.
+pub fn kruskal<I, N, E, S, C>(
+    g: &UnGraph<I, N, E, S>,
+    cost: impl Fn(&E) -> Option<C>,
+) -> Forest<I, C>
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+    C: Weight,
+{
+    let (ids, index) = numbered(g);
+    let mut edges = g
+        .edges()
+        .filter_map(|(a, b, e)| Some((cost(e)?, a, b)))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+
+    let mut forest = SimpleDisjointSet::new(ids.len());
+    let mut tree = Vec::with_capacity(ids.len().saturating_sub(1));
+    let mut weight = C::default();
+    for (c, a, b) in edges {
+        if forest.set_count() == 1 {
+            break;
+        }
+        if forest.union(index[&a], index[&b]) {
+            tree.push((a, b, c));
+            weight += c;
+        }
+    }
+
+    Forest {
+        edges: tree,
+        weight,
+        components: forest.set_count(),
+    }
+}
+
+/// Prim's algorithm, growing a tree from the smallest node of each component
+/// in turn.  An edge can cost less than the last one taken, so it's queued
+/// with `push_any`.
+pub fn prim<I, N, E, S, C>(g: &UnGraph<I, N, E, S>, cost: impl Fn(&E) -> Option<C>) -> Forest<I, C>
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+    C: Weight,
+{
+    let (ids, index) = numbered(g);
+    let mut added = vec![false; ids.len()];
+    let mut tree = Vec::with_capacity(ids.len().saturating_sub(1));
+    let mut weight = C::default();
+    let mut components = 0;
+
+    let mut queue = C::Queue::<(I, Option<I>)>::default();
+    for &root in &ids {
+        if added[index[&root]] {
+            continue;
+        }
+        components += 1;
+        queue.push_any(C::default(), (root, None));
+        while let Some((c, (n, from))) = queue.pop() {
+            if added[index[&n]] {
+                continue;
+            }
+            added[index[&n]] = true;
+            if let Some(from) = from {
+                tree.push((from, n, c));
+                weight += c;
+            }
+            for (m, e) in g.neighbours(n) {
+                if let (false, Some(c)) = (added[index[&m]], cost(e)) {
+                    queue.push_any(c, (m, Some(n)));
+                }
+            }
+        }
+    }
+
+    Forest {
+        edges: tree,
+        weight,
+        components,
+    }
+}
+
+/// Kruskal's algorithm over `nodes` of a metric closure, using the closure's
+/// edge between every pair.
+///
+/// Like `kruskal_mst_weight`, but with the edges, and telling a node missing
+/// from the closure apart from a missing pair.
+pub fn kruskal_complete<I, N, C, S>(
+    metric_closure: &UnGraph<I, N, C, S>,
+    nodes: &[I],
+) -> Result<Forest<I, C>, MstError<I>>
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+    C: Weight,
+{
+    let mut edges = Vec::with_capacity(nodes.len() * nodes.len().saturating_sub(1) / 2);
+    for (i, &n) in nodes.iter().enumerate() {
+        if !metric_closure.contains_node(n) {
+            return Err(MstError::MissingNode(n));
+        }
+        for (j, &m) in nodes[..i].iter().enumerate() {
+            let d = *metric_closure
+                .get_edge(n, m)
+                .ok_or(MstError::MissingEdge(m, n))?;
+            edges.push((d, (j, i)));
+        }
+    }
+    edges.sort_by_key(|x| x.0);
+
+    let mut forest = SimpleDisjointSet::new(nodes.len());
+    let mut tree = Vec::with_capacity(nodes.len().saturating_sub(1));
+    let mut weight = C::default();
+    for (d, (i, j)) in edges {
+        if forest.set_count() == 1 {
+            break;
+        }
+        if forest.union(i, j) {
+            tree.push((nodes[i], nodes[j], d));
+            weight += d;
+        }
+    }
+
+    Ok(Forest {
+        edges: tree,
+        weight,
+        components: forest.set_count(),
+    })
+}
+
+/// The nodes in order, and where each one is in that order.
+fn numbered<I, N, E, S>(g: &UnGraph<I, N, E, S>) -> (Vec<I>, HashMap<I, usize, S>)
+where
+    I: Copy + Ord + Hash,
+    S: BuildHasher + Default,
+{
+    let mut ids = g.node_ids().collect::<Vec<_>>();
+    ids.sort_unstable();
+    let index = ids.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+    (ids, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{make_board, Edge};
+    use crate::graph::{kruskal_mst_weight_usize, metric_closure_usize};
+    use std::collections::hash_map::RandomState;
+
+    /// A square with a diagonal, a separate pair, and a loner.
+    fn islands() -> UnGraph<u8, (), usize, RandomState> {
+        let mut g = UnGraph::with_capacity(7, 6);
+        (0..7).for_each(|n| g.add_node(n, ()));
+        g.add_edge(0, 1, 1);
+        g.add_edge(1, 2, 4);
+        g.add_edge(2, 3, 1);
+        g.add_edge(3, 0, 2);
+        g.add_edge(0, 2, 3);
+        g.add_edge(4, 5, 7);
+        g
+    }
+
+    #[test]
+    fn forests_of_sparse_graphs() {
+        let g = islands();
+        for forest in &[kruskal(&g, |&e| Some(e)), prim(&g, |&e| Some(e))] {
+            assert_eq!(forest.weight, 11);
+            assert_eq!(forest.components, 3);
+            assert_eq!(forest.edges.len(), 4);
+            assert!(!forest.is_tree());
+        }
+
+        // Without the 0-3 edge the diagonal is needed instead
+        let no_side = |&e: &usize| if e == 2 { None } else { Some(e) };
+        assert_eq!(kruskal(&g, no_side).weight, 12);
+        assert_eq!(prim(&g, no_side).weight, 12);
+
+        let empty = UnGraph::<u8, (), usize, RandomState>::with_capacity(0, 0);
+        assert!(kruskal(&empty, |&e| Some(e)).is_tree());
+        assert!(prim(&empty, |&e| Some(e)).edges.is_empty());
+    }
+
+    #[test]
+    fn prim_matches_kruskal_on_the_board() {
+        let board = make_board();
+        let rails = |e: &Edge| e.cost.rails();
+        let (k, p) = (kruskal(&board, rails), prim(&board, rails));
+        assert_eq!((p.weight, p.components), (k.weight, k.components));
+        assert_eq!(p.edges.len(), board.nodes().len() - 1);
+        assert!(p
+            .edges
+            .iter()
+            .all(|&(a, b, c)| rails(board.get_edge(a, b).unwrap()) == Some(c)));
+    }
+
+    #[test]
+    fn complete_reports_missing_pairs() {
+        let closure = metric_closure_usize(&islands(), |&e| Some(e));
+        let forest = kruskal_complete(&closure, &[0, 1, 2, 3]).unwrap();
+        assert_eq!(forest.weight, 4);
+        assert!(forest.is_tree());
+        assert_eq!(
+            Ok(forest.weight),
+            kruskal_mst_weight_usize(&closure, &[0, 1, 2, 3])
+        );
+
+        assert_eq!(
+            kruskal_complete(&closure, &[0, 4]),
+            Err(MstError::MissingEdge(0, 4))
+        );
+        assert_eq!(
+            kruskal_complete(&closure, &[1, 9]),
+            Err(MstError::MissingNode(9))
+        );
+        assert_eq!(kruskal_complete(&closure, &[]).unwrap().weight, 0);
+    }
+}
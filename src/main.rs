@@ -1,17 +1,34 @@
 #![allow(dead_code)]
 
+use cli::{Command, Format};
 use fnv::FnvHashMap;
-use itertools::Itertools;
 use rayon::prelude::*;
+use stats::ParallelVariance;
 use std::collections::BTreeMap;
-use std::iter::FromIterator;
-use std::sync::Mutex;
+use std::io::Write;
 use std::time::Instant;
 
+mod analysis;
+mod ascii;
+mod board_file;
 mod bucket_queue;
+mod cache;
+mod cli;
 mod data;
+mod deal;
+mod export;
+mod fairness;
+mod game;
 mod graph;
+mod map;
+mod pairs;
+mod player;
+mod rng;
+mod stats;
+mod svg;
 mod union_find;
+mod validate;
+mod weight;
 
 fn histogram<T: Ord>(it: impl Iterator<Item = T>) -> BTreeMap<T, usize> {
     let mut counts = BTreeMap::new();
@@ -22,235 +39,853 @@ fn histogram<T: Ord>(it: impl Iterator<Item = T>) -> BTreeMap<T, usize> {
 fn main() {
     let start_instant = Instant::now();
 
-    let g = data::make_board();
-    println!(
-        "Board has {} nodes & {} edges",
-        g.nodes().len(),
-        g.edges().len(),
-    );
-    println!();
+    let (command, options) = match cli::parse(std::env::args().skip(1)) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if command == Command::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if let Some(n) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build_global()
+            .unwrap();
+    }
+    let players = options.players;
 
-    let metric_closure = graph::metric_closure_usize(&g, |e| 0 + e.cost);
+    let map = map::Map::load(&options.map).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    let g = &map.board;
+    if options.format == Format::Text {
+        println!(
+            "Board has {} nodes & {} edges",
+            g.nodes().len(),
+            g.edges().len(),
+        );
+        println!();
+        println!(
+            "Deck for {} players has {} cities",
+            players.get(),
+            map.deck(players).count()
+        );
+        println!();
+    }
 
-    let cities_by_pos = data::CITIES
-        .iter()
-        .map(|x| (x.pos, *x))
-        .collect::<FnvHashMap<_, _>>();
+    let find = |name: &str| {
+        map.find_city(name).unwrap_or_else(|| {
+            eprintln!("no city named {:?}", name);
+            let close = map.suggest_cities(name);
+            if !close.is_empty() {
+                eprintln!("did you mean {}?", close.join(" or "));
+            }
+            std::process::exit(2);
+        })
+    };
+    let parse_hand = |names: &[String]| {
+        let cities = names.iter().map(|x| find(x)).collect::<Vec<_>>();
+        let hand = map.make_hand(&cities).unwrap_or_else(|e| {
+            eprintln!("not a hand: {}", e);
+            std::process::exit(2);
+        });
+        if let Some(c) = cities.iter().find(|c| !map.allows(players, c)) {
+            eprintln!(
+                "{} is not in the deck for {} players",
+                c.name,
+                players.get()
+            );
+            std::process::exit(2);
+        }
+        hand
+    };
 
-    let mut all_positions: Vec<_> = g.node_ids().collect();
-    all_positions.sort_by_cached_key(|&n|
-        data::CITIES.iter()
-            .map(|x| x.pos)
-            .map(|x| if x == n { 0 } else { *metric_closure.get_edge(n, x).unwrap() })
-            .sum::<usize>()
-    );
+    match command {
+        Command::Analyze | Command::Histogram | Command::City(_) => {
+            let ctx = context(&map, &options);
+            let mut all_hands = score_all(&ctx, options.solver, players);
+            all_hands.sort_unstable_by(|a, b| ((a.0).0, &a.1).cmp(&((b.0).0, &b.1)));
+            let all_stats = all_hands
+                .iter()
+                .map(|x| (x.0).0 as f64)
+                .collect::<ParallelVariance>();
 
-    let hands_by_city = Mutex::new(FnvHashMap::<_, Vec<_>>::with_hasher(Default::default()));
-    let mut all_hands = data::hands(None)
-        .par_bridge()
-        .map(|a| {
-            let mut points = a.to_vec();
-            let mut kmst = graph::kruskal_mst_weight_usize(&metric_closure, &points);
-            loop {
-                //eprintln!("{} with {:?}", kmst, points);
-                let best = all_positions
-                    .iter()
-                    .cloned()
-                    .filter_map(|n| {
-                        if points.contains(&n) { return None }
-
-                        points.push(n);
-                        let c = graph::kruskal_mst_weight_usize(&metric_closure, &points);
-                        points.pop();
-                        if c < kmst { Some((c, n))} else { None }
-                    })
-                    .min_by_key(|x| x.0);
-                if let Some((c, n)) = best {
-                    kmst = c;
-                    points.push(n);
-                } else {
-                    break;
+            let city = match &command {
+                Command::City(name) => {
+                    let c = find(name);
+                    if !map.allows(players, c) {
+                        eprintln!(
+                            "{} is not in the deck for {} players",
+                            c.name,
+                            players.get()
+                        );
+                        std::process::exit(2);
+                    }
+                    Some(c)
                 }
-            }
+                _ => None,
+            };
+            let hands = match city {
+                Some(c) => all_hands
+                    .into_iter()
+                    .filter(|x| x.1.contains(&c.pos))
+                    .collect(),
+                None => all_hands,
+            };
+            let cities = match (&command, city) {
+                (_, Some(c)) => vec![c],
+                (Command::Analyze, _) => map.deck(players).collect(),
+                _ => vec![],
+            };
+            let hands_by_city = hands_by_city(&hands);
 
-            points.drain(..5);
-            let hand = ((kmst, points), a);
-            for c in a.iter().cloned() {
-                hands_by_city
-                    .lock()
-                    .unwrap()
-                    .entry(c)
-                    .or_default()
-                    .push(hand.clone());
+            match options.format {
+                Format::Text => {
+                    if city.is_none() {
+                        println!("*** Everything ***");
+                        println!(
+                            "mean: {:.2} stdev: {:.2}",
+                            all_stats.mean(),
+                            all_stats.stdev_pop()
+                        );
+                        let hist = histogram(hands.iter().map(|x| (x.0).0));
+                        println!("histogram: {:?}", hist);
+                        print_extremes(&map, &hands);
+                        println!();
+                    }
+                    for c in cities {
+                        city_report(&map, c, &hands_by_city[&c.pos], all_stats);
+                    }
+                }
+                Format::Csv | Format::JsonLines => {
+                    check_output(write_rows(&ctx, &options, &hands));
+                }
+                Format::Json => {
+                    let summaries = cities
+                        .iter()
+                        .map(|&c| {
+                            let hands = &hands_by_city[&c.pos];
+                            export::CitySummary {
+                                city: c,
+                                hands: hands.len(),
+                                stats: hands.iter().map(|x| (x.0).0 as f64).collect(),
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let stdout = std::io::stdout();
+                    check_output(export::write_summary_json(
+                        &mut stdout.lock(),
+                        players.get(),
+                        options.solver.name(),
+                        hands.len(),
+                        hands.iter().map(|x| (x.0).0 as f64).collect(),
+                        &histogram(hands.iter().map(|x| (x.0).0)),
+                        &summaries,
+                    ));
+                }
+            }
+        }
+        Command::Hand(names) => {
+            let hand = parse_hand(&names);
+            hand_report(&context(&map, &options), &options, hand);
+        }
+        Command::Fairness => {
+            let ctx = context(&map, &options);
+            let hands = score_all(&ctx, options.solver, players);
+            let fairness = fairness::fairness(&map, &hands);
+            match options.format {
+                Format::Csv => {
+                    let stdout = std::io::stdout();
+                    check_output(export::write_fairness_csv(&mut stdout.lock(), &fairness));
+                }
+                _ => fairness_report(&fairness),
+            }
+        }
+        Command::Pairs(file) => {
+            let ctx = context(&map, &options);
+            let hands = score_all(&ctx, options.solver, players);
+            let overall = hands
+                .iter()
+                .map(|x| (x.0).0 as f64)
+                .collect::<ParallelVariance>();
+            let interactions = pairs::interactions(
+                map.deck(players).collect(),
+                overall.mean(),
+                &hands_by_city(&hands),
+            );
+            match options.format {
+                Format::Csv => {
+                    let stdout = std::io::stdout();
+                    check_output(export::write_pairs_csv(&mut stdout.lock(), &interactions));
+                }
+                Format::JsonLines => {
+                    let stdout = std::io::stdout();
+                    check_output(export::write_pairs_jsonl(&mut stdout.lock(), &interactions));
+                }
+                _ => pairs_report(&interactions),
+            }
+            if let Some(file) = file {
+                if let Err(e) = std::fs::write(&file, svg::heatmap(&interactions)) {
+                    eprintln!("{}: {}", file, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Gap => gap_report(&context(&map, &options), players),
+        Command::Bench => bench(&context(&map, &options), players),
+        Command::Starts => start_report(&map, options.costs, players),
+        Command::Deal(deals) => deal_report(
+            &context(&map, &options),
+            options.solver,
+            deals,
+            options.seed,
+        ),
+        Command::Simulate(rounds) => simulate(&map, players, rounds, options.seed),
+        Command::Route(from, to) => {
+            let (from, to) = (find(&from), find(&to));
+            match options.fractional_costs {
+                Some(costs) => route(&map, from, to, costs.edge()),
+                None => route(&map, from, to, options.costs.edge()),
+            }
+        }
+        Command::Board(names) => {
+            let color = options.color.unwrap_or_else(|| {
+                std::io::IsTerminal::is_terminal(&std::io::stdout())
+                    && std::env::var_os("NO_COLOR").is_none()
+            });
+            let text = if names.is_empty() {
+                ascii::render(&map, None, color)
+            } else {
+                let hand = parse_hand(&names);
+                let ctx = context(&map, &options);
+                let eval = evaluate_hand(&ctx, options.solver, &hand);
+                println!("{}: {}", map.city_names(&hand).join(", "), eval.cost);
+                println!();
+                ascii::render(&map, Some((&hand, &eval)), color)
+            };
+            print!("{}", text);
+            println!();
+            println!("{}", ascii::LEGEND);
+        }
+        Command::Validate => {
+            let problems = validate::validate(&map);
+            for p in &problems {
+                println!("{}", p);
             }
-            hand
-        }).collect::<Vec<_>>();
-    let mut hands_by_city = hands_by_city.into_inner().unwrap();
+            if !problems.is_empty() {
+                eprintln!("{}: {} problems", map.name, problems.len());
+                std::process::exit(1);
+            }
+            println!("{}: no problems", map.name);
+        }
+        Command::Render(dir) => {
+            let ctx = context(&map, &options);
+            check_output(render(&ctx, &options, &dir));
+        }
+        Command::Help => unreachable!(),
+    }
+
+    let elapsed = start_instant.elapsed();
+    eprintln!("Done in {:?}", elapsed);
+}
+
+/// One hand's cost and track, ranked against every other hand.
+fn hand_report(ctx: &analysis::Context, options: &cli::Options, hand: map::Hand) {
+    let (solver, players) = (options.solver, options.players);
+    let eval = evaluate_hand(ctx, solver, &hand);
+    println!("*** {} ***", ctx.map.city_names(&hand).join(", "));
+    println!("cost: {} via {:?}", eval.cost, eval.steiner_points);
+
+    let costs = score_all(ctx, solver, players)
+        .into_iter()
+        .map(|x| (x.0).0)
+        .collect::<Vec<_>>();
+    let cheaper = costs.iter().filter(|&&c| c < eval.cost).count();
+    let tied = costs.iter().filter(|&&c| c == eval.cost).count();
+    println!(
+        "percentile: {:.1} ({} of {} hands cheaper, {} as cheap)",
+        100.0 * (cheaper as f64 + tied as f64 / 2.0) / costs.len() as f64,
+        cheaper,
+        costs.len(),
+        tied
+    );
+
+    let mut track = eval
+        .track
+        .edges()
+        .map(|(a, b, _)| (a, b))
+        .collect::<Vec<_>>();
+    track.sort();
+    println!(
+        "track: {} segments costing {}, crossing {} rivers or mountains",
+        track.len(),
+        eval.track_cost(&ctx.map.board, ctx.costs),
+        eval.crossings(&ctx.map.board)
+    );
+    for (a, b) in track {
+        println!("  {:?} - {:?}", a, b);
+    }
+}
+
+/// The cheapest path between two cities, under whole or fractional costs.
+fn route<C: weight::Weight + std::fmt::Display>(
+    map: &map::Map,
+    from: &data::City,
+    to: &data::City,
+    cost: impl Fn(&data::Edge) -> Option<C>,
+) {
+    let paths = graph::dijkstra_paths(&map.board, from.pos, cost);
+    match (paths.distance(to.pos), paths.path_to(to.pos)) {
+        (Some(d), Some(path)) => println!("{} -> {}: {} via {:?}", from.name, to.name, d, path),
+        _ => unconnected(map, graph::NotConnected(from.pos, to.pos)),
+    }
+}
+
+/// Exits naming two cities that no track can join, which only a broken
+/// board file has.
+fn unconnected(map: &map::Map, e: analysis::Unconnected) -> ! {
+    let name = |p| {
+        map.city_at(p)
+            .map_or_else(|| format!("{:?}", p), |c| c.name.to_owned())
+    };
+    eprintln!("no track can join {} and {}", name(e.0), name(e.1));
+    std::process::exit(1);
+}
+
+fn score_all(
+    ctx: &analysis::Context,
+    solver: analysis::Solver,
+    players: data::PlayerCount,
+) -> Vec<analysis::Scored> {
+    ctx.score_all(solver, players)
+        .unwrap_or_else(|e| unconnected(ctx.map, e))
+}
+
+fn evaluate_hand(
+    ctx: &analysis::Context,
+    solver: analysis::Solver,
+    hand: &[data::Position],
+) -> analysis::HandEvaluation {
+    analysis::evaluate_hand(ctx, solver, hand).unwrap_or_else(|e| unconnected(ctx.map, e))
+}
+
+fn hands_by_city(hands: &[analysis::Scored]) -> FnvHashMap<data::Position, Vec<analysis::Scored>> {
+    let mut by_city = FnvHashMap::<_, Vec<_>>::default();
+    for hand in hands {
+        for &c in &hand.1 {
+            by_city.entry(c).or_default().push(hand.clone());
+        }
+    }
+    by_city
+}
+
+/// The analysis context, through the cache unless `--no-cache`.
+fn context<'a>(map: &'a map::Map, options: &cli::Options) -> analysis::Context<'a> {
+    let ctx = match &options.cache {
+        Some(dir) => analysis::Context::cached(map, options.costs, cache::Cache::new(dir)),
+        None => analysis::Context::new(map, options.costs),
+    };
+    ctx.unwrap_or_else(|e| {
+        eprintln!("--costs too large: {}", e);
+        std::process::exit(2);
+    })
+}
+
+/// Quietly stops if stdout was closed early, as when piped into `head`.
+fn check_output(r: std::io::Result<()>) {
+    match r {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            eprintln!("error writing output: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes one CSV or JSON Lines row per hand, with track if asked for.
+fn write_rows(
+    ctx: &analysis::Context,
+    options: &cli::Options,
+    hands: &[analysis::Scored],
+) -> std::io::Result<()> {
+    let stdout = std::io::stdout();
+    let mut w = std::io::BufWriter::new(stdout.lock());
+    if options.format == Format::Csv {
+        export::write_csv_header(&mut w, ctx.map)?;
+    }
+
+    let tracks = if options.track {
+        hands
+            .par_iter()
+            .map(|x| {
+                let eval = evaluate_hand(ctx, options.solver, &x.1);
+                let mut track = eval
+                    .track
+                    .edges()
+                    .map(|(a, b, _)| (a, b))
+                    .collect::<Vec<_>>();
+                track.sort();
+                Some(track)
+            })
+            .collect()
+    } else {
+        vec![None; hands.len()]
+    };
+
+    for (((cost, points), hand), track) in hands.iter().zip(&tracks) {
+        let row = export::HandRow {
+            hand,
+            cost: *cost,
+            steiner_points: points,
+            track: track.as_ref().map(Vec::as_slice),
+        };
+        match options.format {
+            Format::Csv => export::write_csv_row(&mut w, ctx.map, &row)?,
+            _ => export::write_jsonl_row(&mut w, ctx.map, &row)?,
+        }
+    }
+    w.flush()
+}
+
+/// Writes the bare board, then the best, median and worst hands overall and
+/// for each city, as SVG files in `dir`.
+fn render(ctx: &analysis::Context, options: &cli::Options, dir: &str) -> std::io::Result<()> {
+    let dir = std::path::Path::new(dir);
+    std::fs::create_dir_all(dir)?;
+    let board = svg::render::<fnv::FnvBuildHasher>(ctx.map, None);
+    std::fs::write(dir.join("board.svg"), board)?;
+
+    let mut all_hands = score_all(ctx, options.solver, options.players);
+    all_hands.sort_unstable_by(|a, b| ((a.0).0, &a.1).cmp(&((b.0).0, &b.1)));
+    let hands_by_city = hands_by_city(&all_hands);
+    let mut groups = vec![("everything".to_owned(), &all_hands)];
+    for c in ctx.map.deck(options.players) {
+        let slug = c.name.to_lowercase().replace('.', "").replace(' ', "-");
+        groups.push((slug, &hands_by_city[&c.pos]));
+    }
+
+    let mut written = 1;
+    for (slug, hands) in groups {
+        let picks = [
+            ("best", hands.first().unwrap()),
+            ("median", &hands[hands.len() / 2]),
+            ("worst", hands.last().unwrap()),
+        ];
+        for &(which, hand) in &picks {
+            let eval = evaluate_hand(ctx, options.solver, &hand.1);
+            let overlay = svg::Overlay {
+                title: format!(
+                    "{} {}: {} ({})",
+                    slug,
+                    which,
+                    ctx.map.city_names(&hand.1).join(", "),
+                    eval.cost
+                ),
+                hand: &hand.1,
+                track: &eval.track,
+                steiner_points: &eval.steiner_points,
+            };
+            let path = dir.join(format!("{}-{}.svg", slug, which));
+            std::fs::write(path, svg::render(ctx.map, Some(&overlay)))?;
+            written += 1;
+        }
+    }
+    println!("wrote {} files to {}", written, dir.display());
+    Ok(())
+}
+
+/// Prints the best, median and worst of `hands`, which must be sorted.
+fn print_extremes(map: &map::Map, hands: &[analysis::Scored]) {
+    let best = hands.first().unwrap();
+    println!(
+        "best: {:?} via {:?} {:?}",
+        (best.0).0,
+        (best.0).1,
+        map.city_names(&best.1)
+    );
+    let median = &hands[hands.len() / 2];
+    println!(
+        "median: {:?} via {:?} {:?}",
+        (median.0).0,
+        (median.0).1,
+        map.city_names(&median.1)
+    );
+    let worst = hands.last().unwrap();
+    println!(
+        "worst: {:?} via {:?} {:?}",
+        (worst.0).0,
+        (worst.0).1,
+        map.city_names(&worst.1)
+    );
+}
 
-    println!("*** Everything ***");
-    all_hands.sort_unstable_by_key(|x| ((x.0).0, x.1));
-    let all_stats = all_hands
+/// The per-city section of the report; `hands` are those holding `c`, sorted.
+fn city_report(
+    map: &map::Map,
+    c: &data::City,
+    hands: &[analysis::Scored],
+    all_stats: ParallelVariance,
+) {
+    println!("*** {:?}: {} ({:?}) ***", c.color, c.name, c.pos);
+    let stats = hands
         .iter()
         .map(|x| (x.0).0 as f64)
         .collect::<ParallelVariance>();
     println!(
-        "mean: {:.2} stdev: {:.2}",
-        all_stats.mean(),
-        all_stats.stdev_pop()
+        "mean: {:.2} ({:+.1}) stdev: {:.2} ({:+.1})",
+        stats.mean(),
+        stats.mean() - all_stats.mean(),
+        stats.stdev_pop(),
+        stats.stdev_pop() - all_stats.stdev_pop(),
     );
-    {
-        let hist = histogram(all_hands.iter().map(|x| (x.0).0));
-        println!("histogram: {:?}", hist);
-        let best = all_hands.first().unwrap();
-        println!(
-            "best: {:?} via {:?} {:?}",
-            (best.0).0,
-            (best.0).1,
-            best.1
-                .iter()
-                .map(|x| cities_by_pos.get(x).unwrap().name)
-                .collect::<Vec<_>>()
-        );
-        let median = &all_hands[all_hands.len() / 2];
-        println!(
-            "median: {:?} via {:?} {:?}",
-            (median.0).0,
-            (median.0).1,
-            median
-                .1
-                .iter()
-                .map(|x| cities_by_pos.get(x).unwrap().name)
-                .collect::<Vec<_>>()
-        );
-        let worst = all_hands.last().unwrap();
+    print_extremes(map, hands);
+}
+
+fn fairness_report(f: &fairness::Fairness) {
+    let quantiles = |d: &stats::Distribution| {
+        fairness::QUANTILES
+            .iter()
+            .map(|&p| format!("{:3}", d.counts.quantile(p).unwrap()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let m = f.overall.moments;
+    println!("*** Fairness ***");
+    println!(
+        "all hands: mean {:.2} stdev {:.2} skewness {:+.2} kurtosis {:+.2}",
+        m.mean(),
+        m.stdev_pop(),
+        m.skewness(),
+        m.kurtosis()
+    );
+    println!("p10 p25 p50 p75 p90: {}", quantiles(&f.overall));
+    println!(
+        "cheap hands cost at most {}: {:.1}% of hands",
+        f.cheap_cutoff,
+        100.0 * f.overall.counts.fraction_at_most(f.cheap_cutoff)
+    );
+    println!();
+
+    println!(
+        "{:16} {:6} {:>6} {:>8} {:>5} {:>5} {:>5} {:>3} {:>3} {:>3} {:>3} {:>3} {:>6}",
+        "city",
+        "color",
+        "mean",
+        "marginal",
+        "stdev",
+        "skew",
+        "kurt",
+        "p10",
+        "p25",
+        "p50",
+        "p75",
+        "p90",
+        "cheap"
+    );
+    for c in &f.cities {
+        let m = c.costs.moments;
         println!(
-            "worst: {:?} via {:?} {:?}",
-            (worst.0).0,
-            (worst.0).1,
-            worst
-                .1
-                .iter()
-                .map(|x| cities_by_pos.get(x).unwrap().name)
-                .collect::<Vec<_>>()
+            "{:16} {:6} {:6.2} {:+8.2} {:5.2} {:+5.2} {:+5.2} {} {:5.1}%",
+            c.city.name,
+            format!("{:?}", c.city.color),
+            m.mean(),
+            c.marginal,
+            m.stdev_pop(),
+            m.skewness(),
+            m.kurtosis(),
+            quantiles(&c.costs),
+            100.0 * c.cheap
         );
-        println!();
     }
+    println!();
 
-    for c in data::CITIES {
-        println!("*** {:?}: {} ({:?}) ***", c.color, c.name, c.pos);
-        let hands = hands_by_city.get_mut(&c.pos).unwrap();
-        // let avg = hands.iter().map(|x| (x.0).0).sum::<usize>() as f32 / hands.len() as f32;
-        // println!("average: {:?}", avg);
-        hands.sort_unstable_by_key(|x| ((x.0).0, x.1));
-        let stats = hands
-            .iter()
-            .map(|x| (x.0).0 as f64)
-            .collect::<ParallelVariance>();
+    println!("color  spread marginal stdev  best -> worst");
+    for c in &f.colors {
         println!(
-            "mean: {:.2} ({:+.1}) stdev: {:.2} ({:+.1})",
-            stats.mean(),
-            stats.mean() - all_stats.mean(),
-            stats.stdev_pop(),
-            stats.stdev_pop() - all_stats.stdev_pop(),
+            "{:6} {:6.2} {:14.2}  {} -> {}",
+            format!("{:?}", c.color),
+            c.spread,
+            c.marginal_stdev,
+            c.best.name,
+            c.worst.name
         );
-        let best = hands.first().unwrap();
+    }
+}
+
+/// The pairs that combine best and worst.
+fn pairs_report(x: &pairs::Interactions) {
+    const SHOWN: usize = 10;
+    let mut pairs = x.unique_pairs().collect::<Vec<_>>();
+    pairs.sort_by(|a, b| (a.2).effect.partial_cmp(&(b.2).effect).unwrap());
+    let print = |&(i, j, p): &(usize, usize, pairs::Pair)| {
         println!(
-            "best: {:?} via {:?} {:?}",
-            (best.0).0,
-            (best.0).1,
-            best.1
-                .iter()
-                .map(|x| cities_by_pos.get(x).unwrap().name)
-                .collect::<Vec<_>>()
+            "{:>16} + {:16} mean {:.2} effect {:+.2} ({:.2} and {:.2} alone)",
+            x.cities[i].name, x.cities[j].name, p.mean, p.effect, x.means[i], x.means[j]
         );
-        let median = &hands[hands.len() / 2];
+    };
+    println!("*** Pairs ***");
+    println!("{} pairs of cities of different colours", pairs.len());
+    println!();
+    println!("Best together:");
+    pairs.iter().take(SHOWN).for_each(print);
+    println!();
+    println!("Worst together:");
+    pairs.iter().rev().take(SHOWN).for_each(print);
+}
+
+fn gap_report(ctx: &analysis::Context, players: data::PlayerCount) {
+    let mut gaps = ctx
+        .map
+        .hands(players)
+        .par_bridge()
+        .map(|a| {
+            let score = |solver| {
+                ctx.score(solver, &a)
+                    .unwrap_or_else(|e| unconnected(ctx.map, e))
+            };
+            let (heuristic, _) = score(analysis::Solver::Heuristic);
+            let (exact, _) = score(analysis::Solver::Exact);
+            assert!(exact <= heuristic, "exact solver beaten for {:?}", a);
+            (heuristic - exact, heuristic, exact, a)
+        })
+        .collect::<Vec<_>>();
+    gaps.sort_unstable_by(|a, b| (b.0, &a.3).cmp(&(a.0, &b.3)));
+
+    println!("*** Heuristic vs exact ***");
+    println!("gap heuristic exact hand");
+    for (gap, heuristic, exact, a) in gaps.iter().take_while(|x| x.0 > 0) {
         println!(
-            "median: {:?} via {:?} {:?}",
-            (median.0).0,
-            (median.0).1,
-            median
-                .1
-                .iter()
-                .map(|x| cities_by_pos.get(x).unwrap().name)
-                .collect::<Vec<_>>()
+            "{:3} {:9} {:5} {:?}",
+            gap,
+            heuristic,
+            exact,
+            ctx.map.city_names(a)
         );
-        let worst = hands.last().unwrap();
+    }
+    println!();
+
+    let differ = gaps.iter().filter(|x| x.0 > 0).count();
+    println!(
+        "differ: {} of {} ({:.2}%)",
+        differ,
+        gaps.len(),
+        100.0 * differ as f64 / gaps.len() as f64
+    );
+    println!("max gap: {}", gaps.first().map_or(0, |x| x.0));
+    println!("gap histogram: {:?}", histogram(gaps.iter().map(|x| x.0)));
+    println!("exact histogram: {:?}", histogram(gaps.iter().map(|x| x.2)));
+    println!("worst offenders:");
+    for (gap, heuristic, exact, a) in gaps.iter().take(10).take_while(|x| x.0 > 0) {
         println!(
-            "worst: {:?} via {:?} {:?}",
-            (worst.0).0,
-            (worst.0).1,
-            worst
-                .1
-                .iter()
-                .map(|x| cities_by_pos.get(x).unwrap().name)
-                .collect::<Vec<_>>()
+            "  +{} ({} vs {}) {:?}",
+            gap,
+            heuristic,
+            exact,
+            ctx.map.city_names(a)
         );
     }
-
-    let elapsed = start_instant.elapsed();
-    eprintln!("Done in {:?}", elapsed);
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-struct ParallelVariance {
-    n: f64,
-    x: f64,
-    m: f64,
-}
-
-impl ParallelVariance {
-    fn mean(self) -> f64 {
-        self.x
+/// Times `one_steiner` on every hand against the versions it replaced: with
+/// a Kruskal from scratch per candidate, and over the hashed metric closure.
+/// Checks that they all agree.
+fn bench(ctx: &analysis::Context, players: data::PlayerCount) {
+    let hashed = graph::metric_closure_usize(&ctx.map.board, ctx.costs.edge());
+    let candidates = ctx
+        .candidates
+        .iter()
+        .map(|&n| ctx.metric_closure.id(n))
+        .collect::<Vec<_>>();
+    let hands = ctx.map.hands(players).collect::<Vec<_>>();
+    type OneSteiner<'a> = &'a (dyn Fn(&[data::Position]) -> Result<(usize, Vec<data::Position>), analysis::Unconnected>
+             + Sync);
+    let versions: [(&str, OneSteiner); 3] = [
+        ("from scratch", &|hand| {
+            analysis::one_steiner_from_scratch(&hashed, &candidates, hand)
+        }),
+        ("hashed", &|hand| {
+            analysis::one_steiner(&hashed, &candidates, hand)
+        }),
+        ("dense", &|hand| ctx.one_steiner(hand)),
+    ];
+    let mut results = Vec::new();
+    for &(name, one_steiner) in &versions {
+        let start = Instant::now();
+        let scored = hands
+            .par_iter()
+            .map(|hand| one_steiner(hand))
+            .collect::<Vec<_>>();
+        let elapsed = start.elapsed();
+        println!(
+            "{:12}: {:?} for {} hands, {:?} per hand",
+            name,
+            elapsed,
+            hands.len(),
+            elapsed / hands.len() as u32
+        );
+        results.push(scored);
     }
-
-    fn sum(self) -> f64 {
-        self.n * self.x
+    if results.iter().all(|x| *x == results[0]) {
+        println!("same costs and Steiner points for every hand");
+    } else {
+        println!("the versions disagree!");
+        std::process::exit(1);
     }
+}
 
-    fn var_corrected(self, c: f64) -> f64 {
-        self.m / (self.n - c)
-    }
+fn start_report(map: &map::Map, costs: data::CostModel, players: data::PlayerCount) {
+    let results = map
+        .hands(players)
+        .par_bridge()
+        .map(|a| {
+            let ranked = analysis::rank_starts(&map.board, costs, &a)
+                .unwrap_or_else(|e| unconnected(map, e));
+            let best = ranked[0];
+            let optimal = ranked
+                .iter()
+                .take_while(|x| (x.cost, x.turns) == (best.cost, best.turns));
+            let cities = optimal.clone().filter(|x| a.contains(&x.pos)).count();
+            let interior = optimal.count() - cities;
+            let penalty = ranked.iter().map(|x| x.cost - best.cost).sum::<usize>() as f64;
+            (cities, interior, penalty / ranked.len() as f64)
+        })
+        .collect::<Vec<_>>();
 
-    fn var_pop(self) -> f64 {
-        self.var_corrected(0.0)
-    }
+    let n = results.len();
+    println!("*** Starting marker placement ***");
+    println!("best: cheapest, then fewest turns to build from");
+    let count = |f: &dyn Fn(&(usize, usize, f64)) -> bool| {
+        let k = results.iter().filter(|x| f(x)).count();
+        format!("{} of {} ({:.2}%)", k, n, 100.0 * k as f64 / n as f64)
+    };
+    println!("a city is among the best: {}", count(&|x| x.0 > 0));
+    println!(
+        "an interior node is among the best: {}",
+        count(&|x| x.1 > 0)
+    );
+    println!("only interior nodes are best: {}", count(&|x| x.0 == 0));
+    println!(
+        "best cities per hand: {:?}",
+        histogram(results.iter().map(|x| x.0))
+    );
+    println!(
+        "best interior nodes per hand: {:?}",
+        histogram(results.iter().map(|x| x.1))
+    );
+    let penalty = results.iter().map(|x| x.2).collect::<ParallelVariance>();
+    println!(
+        "cost of a uniformly random start: mean {:+.2} stdev {:.2}",
+        penalty.mean(),
+        penalty.stdev_pop()
+    );
+}
 
-    fn stdev_corrected(self, c: f64) -> f64 {
-        self.var_corrected(c).sqrt()
-    }
+/// Hand costs under the real deal, for every player count the map can deal.
+fn deal_report(ctx: &analysis::Context, solver: analysis::Solver, deals: usize, seed: u64) {
+    let map = ctx.map;
+    let scored = score_all(ctx, solver, map.full_deck)
+        .into_iter()
+        .map(|((cost, _), hand)| (hand, cost))
+        .collect::<FnvHashMap<_, _>>();
+    let mut rng = rng::Rng::new(seed);
+    let quantiles = |d: &stats::Distribution| {
+        fairness::QUANTILES
+            .iter()
+            .map(|&p| format!("{:3}", d.counts.quantile(p).unwrap()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
 
-    fn stdev_pop(self) -> f64 {
-        self.stdev_corrected(0.0)
+    println!("*** Deals ***");
+    println!(
+        "{:7} | {:>5} {:>5} {:>19} | {:>5} {:>5} {:>19}",
+        "", "hand", "", "", "table", "spread", ""
+    );
+    println!(
+        "{:7} | {:>5} {:>5} {:>19} | {:>5} {:>5} {:>19}",
+        "players", "mean", "stdev", "p10 p25 p50 p75 p90", "mean", "stdev", "p10 p25 p50 p75 p90"
+    );
+    for &players in &data::PlayerCount::ALL {
+        let d = match deal::deals(map, &scored, players, deals, &mut rng) {
+            Ok(d) => d,
+            Err(e) => {
+                println!("{:7} | {}", players.get(), e);
+                continue;
+            }
+        };
+        println!(
+            "{:7} | {:5.2} {:5.2} {} | {:5.2} {:5.2} {}",
+            players.get(),
+            d.hand.moments.mean(),
+            d.hand.moments.stdev_pop(),
+            quantiles(&d.hand),
+            d.spread.moments.mean(),
+            d.spread.moments.stdev_pop(),
+            quantiles(&d.spread)
+        );
     }
+    println!();
+    println!("hand: any one player's hand, exactly");
+    println!(
+        "table spread: dearest minus cheapest hand dealt, over {} deals",
+        deals
+    );
+}
 
-    fn standardize_corrected(self, x: f64, c: f64) -> f64 {
-        let s = self.stdev_corrected(c);
-        (x - self.mean()) / s
+/// Plays the built-in strategies against each other, rotating seats.
+fn simulate(map: &map::Map, players: data::PlayerCount, rounds: usize, seed: u64) {
+    if let Err(e) = deal::check(map, players) {
+        eprintln!("{}", e);
+        std::process::exit(2);
     }
-
-    fn standardize_pop(self, x: f64) -> f64 {
-        self.standardize_corrected(x, 0.0)
+    // The players count on reaching every city
+    let deck = map.deck(players).map(|c| c.pos).collect::<Vec<_>>();
+    let reached = graph::dijkstra_usize(&map.board, deck[0], |e| e.cost.rails());
+    if let Some(&c) = deck.iter().find(|&&c| !reached.contains_node(c)) {
+        unconnected(map, graph::NotConnected(deck[0], c));
     }
 
-    fn merge(a: Self, b: Self) -> Self {
-        let n = a.n + b.n;
-        let x = (a.sum() + b.sum()) / n;
-        let m = (a.m + b.m) + (a.x - b.x).powi(2) * (a.n * b.n) / n;
-        Self { n, x, m }
-    }
-}
+    let mut rng = rng::Rng::new(seed);
+    let strategies = player::STRATEGIES;
+    let mut names = vec![String::new(); strategies.len()];
+    let mut seats = vec![0; strategies.len()];
+    let mut wins = vec![0; strategies.len()];
+    let mut lost = vec![0; strategies.len()];
+    for round in 0..rounds {
+        let hands = deal::deal(map, players, &mut rng);
 
-impl From<f64> for ParallelVariance {
-    fn from(x: f64) -> Self {
-        Self { n: 1.0, x, m: 0.0 }
+        // Seat i is played by strategy (i + round) % strategies.len()
+        let strategy = |seat: usize| (seat + round) % strategies.len();
+        let mut table = (0..players.get())
+            .map(|seat| strategies[strategy(seat)](rng.next_u64()))
+            .collect::<Vec<_>>();
+        let result = player::play_round(&map.board, hands, &mut table);
+
+        for (seat, p) in table.iter().enumerate() {
+            let s = strategy(seat);
+            names[s] = p.name().to_owned();
+            seats[s] += 1;
+            lost[s] += result.lost[seat];
+            if result.winner == Some(seat) {
+                wins[s] += 1;
+            }
+        }
     }
-}
 
-impl FromIterator<f64> for ParallelVariance {
-    fn from_iter<I: IntoIterator<Item = f64>>(it: I) -> Self {
-        it.into_iter()
-            .map(|x| x.into())
-            .tree_fold1(ParallelVariance::merge)
-            .unwrap_or_default()
+    println!("*** {} rounds ***", rounds);
+    for i in 0..strategies.len() {
+        println!(
+            "{}: {} wins in {} seats, {:.2} lost per seat",
+            names[i],
+            wins[i],
+            seats[i],
+            lost[i] as f64 / seats[i] as f64
+        );
     }
 }
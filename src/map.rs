@@ -0,0 +1,178 @@
+use crate::board_file;
+use crate::data::{BoardGraph, City, Color, PlayerCount, Position};
+use itertools::Itertools;
+
+/// One city of each of a map's colours, in the order of `Map::colors`.
+pub type Hand = Vec<Position>;
+
+/// The maps that can be chosen by name, each with its board file.
+///
+/// Only Transamerica so far.  Transeuropa and Vexation still have to be
+/// transcribed from their boards; until then they can be loaded as board
+/// files like any other map, and `validate` checks them either way.
+pub const BUILT_IN: &[(&str, &str)] = &[("transamerica", board_file::DEFAULT)];
+
+/// Everything that differs between games in the family: the board, the
+/// cities on it, the colours dealt, and which cities fewer players leave out.
+pub struct Map {
+    pub name: String,
+    pub board: BoardGraph,
+    pub cities: Vec<City>,
+    /// The colours dealt, in the order hands list their cities.
+    pub colors: Vec<Color>,
+    /// Dashed cities are only in the deck with at least this many players.
+    pub full_deck: PlayerCount,
+}
+
+impl Map {
+    pub fn built_in(name: &str) -> Option<Self> {
+        let &(name, text) = BUILT_IN.iter().find(|x| x.0.eq_ignore_ascii_case(name))?;
+        Some(board_file::parse(name, text).expect("built-in map is valid"))
+    }
+
+    pub fn transamerica() -> Self {
+        Self::built_in("transamerica").unwrap()
+    }
+
+    /// A built-in map by name, or else a board file.
+    pub fn load(name_or_path: &str) -> Result<Self, String> {
+        match Self::built_in(name_or_path) {
+            Some(map) => Ok(map),
+            None if std::path::Path::new(name_or_path).exists() => board_file::load(name_or_path),
+            None => {
+                let names = BUILT_IN.iter().map(|x| x.0).collect::<Vec<_>>();
+                Err(format!(
+                    "no map or board file named {:?}; the built-in maps are {}",
+                    name_or_path,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+
+    pub fn city_at(&self, pos: Position) -> Option<&City> {
+        self.cities.iter().find(|x| x.pos == pos)
+    }
+
+    /// Looks a city up by name, ignoring case.
+    pub fn find_city(&self, name: &str) -> Option<&City> {
+        self.cities
+            .iter()
+            .find(|x| x.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Cities whose names are close to `name`, closest first, for typos.
+    pub fn suggest_cities(&self, name: &str) -> Vec<&str> {
+        let name = name.to_lowercase();
+        let mut close = self
+            .cities
+            .iter()
+            .map(|x| {
+                let lower = x.name.to_lowercase();
+                let d = if lower.starts_with(&name) || name.starts_with(&lower) {
+                    0
+                } else {
+                    edit_distance(&lower, &name)
+                };
+                (d, x.name)
+            })
+            .filter(|&(d, n)| d <= 2.max(n.len() / 3))
+            .collect::<Vec<_>>();
+        close.sort();
+        close.into_iter().take(3).map(|x| x.1).collect()
+    }
+
+    /// Dashed cities are left out of the deck with fewer than `full_deck`
+    /// players.
+    pub fn allows(&self, players: PlayerCount, city: &City) -> bool {
+        !city.dashed || players >= self.full_deck
+    }
+
+    /// The city cards in play for this many players.
+    pub fn deck(&self, players: PlayerCount) -> impl DoubleEndedIterator<Item = &City> + Clone {
+        self.cities.iter().filter(move |x| self.allows(players, x))
+    }
+
+    /// Puts one city of each colour into the order `hands` uses.
+    pub fn make_hand(&self, cities: &[&City]) -> Result<Hand, String> {
+        let mut hand = Hand::with_capacity(self.colors.len());
+        for &color in &self.colors {
+            let mut matching = cities.iter().filter(|x| x.color == color);
+            hand.push(match (matching.next(), matching.next()) {
+                (Some(c), None) => c.pos,
+                (None, _) => return Err(format!("no {:?} city", color)),
+                (Some(a), Some(b)) => {
+                    return Err(format!("both {} and {} are {:?}", a.name, b.name, color))
+                }
+            });
+        }
+        if cities.len() != hand.len() {
+            return Err(format!(
+                "a hand is {} cities, not {}",
+                hand.len(),
+                cities.len()
+            ));
+        }
+        Ok(hand)
+    }
+
+    pub fn city_names(&self, hand: &[Position]) -> Vec<&str> {
+        hand.iter()
+            .map(|&x| self.city_at(x).expect("not a city").name)
+            .collect()
+    }
+
+    /// Every hand that can be dealt to this many players: one city of each
+    /// colour, in `colors` order.
+    pub fn hands(&self, players: PlayerCount) -> impl Iterator<Item = Hand> + Clone + '_ {
+        self.colors
+            .iter()
+            .map(|&c| {
+                self.deck(players)
+                    .filter(move |x| x.color == c)
+                    .map(|x| x.pos)
+            })
+            .multi_cartesian_product()
+    }
+}
+
+/// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, x) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let substitute = diagonal + (x != y) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transamerica_decks() {
+        let map = Map::transamerica();
+        assert_eq!(map.hands(PlayerCount::Six).count(), 7usize.pow(5));
+        assert_eq!(map.hands(PlayerCount::Three).count(), 5usize.pow(5));
+
+        let names = ["boston", "Seattle", "new orleans", "Bismark", "santa fe"];
+        let cities = names
+            .iter()
+            .map(|x| map.find_city(x).unwrap())
+            .collect::<Vec<_>>();
+        let hand = map.make_hand(&cities).unwrap();
+        assert_eq!(
+            map.city_names(&hand),
+            ["Seattle", "New Orleans", "Santa Fe", "Bismark", "Boston"]
+        );
+        assert!(map.make_hand(&cities[1..]).is_err());
+        assert_eq!(map.suggest_cities("bostn"), ["Boston"]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::analysis::Scored;
+use crate::data::{City, Position};
+use crate::stats::ParallelVariance;
+use fnv::FnvHashMap;
+use rayon::prelude::*;
+
+/// The hands holding both of two cities.
+#[derive(Debug, Clone, Copy)]
+pub struct Pair {
+    pub hands: usize,
+    pub mean: f64,
+    /// How much dearer the pair is than the two cities' own means predict:
+    /// negative means they combine well.
+    pub effect: f64,
+}
+
+/// A symmetric matrix of `Pair`s over a deck.
+pub struct Interactions<'a> {
+    pub cities: Vec<&'a City>,
+    /// Mean cost of the hands holding each city.
+    pub means: Vec<f64>,
+    /// `None` on the diagonal and between cities of the same colour, which
+    /// never share a hand.
+    pub pairs: Vec<Vec<Option<Pair>>>,
+}
+
+impl<'a> Interactions<'a> {
+    /// Each pair once, as `(i, j, pair)` with `i < j`.
+    pub fn unique_pairs(&self) -> impl Iterator<Item = (usize, usize, Pair)> + '_ {
+        self.pairs.iter().enumerate().flat_map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .skip(i + 1)
+                .filter_map(move |(j, x)| x.map(|x| (i, j, x)))
+        })
+    }
+}
+
+/// Every pair of `cities`, from the hands holding each city (as `main`'s
+/// `hands_by_city` gives) and the mean cost of all the hands.
+///
+/// The effect is against an additive model: the pair should cost
+/// `mean(a) + mean(b) - overall`, each city's excess over the overall mean
+/// adding up.
+pub fn interactions<'a>(
+    cities: Vec<&'a City>,
+    overall: f64,
+    hands_by_city: &FnvHashMap<Position, Vec<Scored>>,
+) -> Interactions<'a> {
+    let rows = cities
+        .par_iter()
+        .map(|a| {
+            let hands = &hands_by_city[&a.pos];
+            let mut with = FnvHashMap::<Position, ParallelVariance>::default();
+            for ((cost, _), hand) in hands {
+                for &b in hand {
+                    let s = with.entry(b).or_default();
+                    *s = ParallelVariance::merge(*s, (*cost as f64).into());
+                }
+            }
+            let mean = with[&a.pos].mean();
+            (mean, with)
+        })
+        .collect::<Vec<_>>();
+
+    let means = rows.iter().map(|x| x.0).collect::<Vec<_>>();
+    let pairs = rows
+        .iter()
+        .enumerate()
+        .map(|(i, (_, with))| {
+            cities
+                .iter()
+                .enumerate()
+                .map(|(j, b)| {
+                    if i == j {
+                        return None;
+                    }
+                    let s = with.get(&b.pos)?;
+                    Some(Pair {
+                        hands: s.count() as usize,
+                        mean: s.mean(),
+                        effect: s.mean() - (means[i] + means[j] - overall),
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    Interactions {
+        cities,
+        means,
+        pairs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Color;
+
+    fn city(name: &'static str, color: Color, x: u8) -> City {
+        City {
+            pos: Position(x, 0),
+            color,
+            name,
+            dashed: false,
+        }
+    }
+
+    #[test]
+    fn effect_spreads_one_synergy() {
+        let cities = [
+            city("A", Color::RED, 0),
+            city("B", Color::RED, 1),
+            city("C", Color::BLUE, 2),
+            city("D", Color::BLUE, 3),
+        ];
+        // Costs add up, except that B and D together save 2
+        let cost = |x: &City, y: &City| {
+            (x.pos.0 as usize + 1) * 10 + y.pos.0 as usize
+                - if x.pos.0 == 1 && y.pos.0 == 3 { 2 } else { 0 }
+        };
+        let mut by_city = FnvHashMap::<_, Vec<_>>::default();
+        let mut all = ParallelVariance::default();
+        for x in &cities[..2] {
+            for y in &cities[2..] {
+                let hand: Scored = ((cost(x, y), vec![]), vec![x.pos, y.pos]);
+                all = ParallelVariance::merge(all, ((hand.0).0 as f64).into());
+                by_city.entry(x.pos).or_default().push(hand.clone());
+                by_city.entry(y.pos).or_default().push(hand);
+            }
+        }
+
+        let x = interactions(cities.iter().collect(), all.mean(), &by_city);
+        assert!(x.pairs[0][1].is_none() && x.pairs[2][2].is_none());
+        assert_eq!(x.unique_pairs().count(), 4);
+        for (i, j, p) in x.unique_pairs() {
+            assert_eq!(p.hands, 1);
+            let expected = if (i, j) == (1, 3) || (i, j) == (0, 2) {
+                -0.5
+            } else {
+                0.5
+            };
+            assert!((p.effect - expected).abs() < 1e-9, "{} {} {:?}", i, j, p);
+        }
+    }
+}
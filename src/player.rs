@@ -0,0 +1,294 @@
+use crate::analysis;
+use crate::data::{BoardGraph, CostModel, Position};
+use crate::game::{Game, RAILS_PER_TURN};
+use crate::graph;
+use crate::map::Hand;
+use crate::rng::Rng;
+use itertools::Itertools;
+
+/// A strategy for one seat at the table.
+///
+/// Everything a player may look at (board, shared network, their own hand)
+/// is available through `game`; `me` is their seat.
+pub trait Player {
+    fn name(&self) -> &str;
+
+    fn choose_start(&mut self, game: &Game, me: usize) -> Position;
+
+    /// Segments to build this turn, in order; see `Game::play_turn`.
+    fn choose_turn(&mut self, game: &Game, me: usize) -> Vec<(Position, Position)>;
+}
+
+/// Builds up a turn one segment at a time: `pick` sees the game as it would
+/// be after the segments chosen so far and the rail units still available.
+fn build_turn(
+    game: &Game,
+    me: usize,
+    mut pick: impl FnMut(&Game, usize) -> Option<(Position, Position)>,
+) -> Vec<(Position, Position)> {
+    let mut turn = Vec::new();
+    loop {
+        let (trial, used) = game
+            .preview_turn(me, &turn)
+            .expect("picked an illegal segment");
+        if used == RAILS_PER_TURN {
+            break;
+        }
+        match pick(&trial, RAILS_PER_TURN - used) {
+            Some(x) => turn.push(x),
+            None => break,
+        }
+    }
+    turn
+}
+
+/// Rail units for a segment, with anything unbuildable over every budget.
+fn segment_cost(game: &Game, (a, b): (Position, Position)) -> usize {
+    game.board()
+        .get_edge(a, b)
+        .and_then(|e| e.cost.rails())
+        .unwrap_or(usize::MAX)
+}
+
+/// Heads for whichever of its unconnected cities is currently cheapest to
+/// reach, treating all existing track as free.
+#[derive(Debug, Default)]
+pub struct GreedyPlayer;
+
+impl Player for GreedyPlayer {
+    fn name(&self) -> &str {
+        "greedy"
+    }
+
+    fn choose_start(&mut self, game: &Game, me: usize) -> Position {
+        let hand = game.hand(me);
+        *hand
+            .iter()
+            .min_by_key(|&&c| {
+                let distances = graph::dijkstra_usize(game.board(), c, |e| e.cost.rails());
+                hand.iter()
+                    .map(|&x| distances.get_node(x).unwrap())
+                    .sum::<usize>()
+            })
+            .unwrap()
+    }
+
+    fn choose_turn(&mut self, game: &Game, me: usize) -> Vec<(Position, Position)> {
+        let start = game.start(me).unwrap();
+        build_turn(game, me, |trial, budget| {
+            let reachable = trial.reachable(me);
+            let board = trial.remaining_board();
+            let paths = graph::dijkstra_paths_usize(&board, start, |e| e.cost.rails());
+            let target = trial
+                .hand(me)
+                .iter()
+                .cloned()
+                .filter(|c| !reachable.contains(c))
+                .min_by_key(|&c| (paths.distance(c).unwrap(), c))?;
+            paths
+                .path_to(target)
+                .unwrap()
+                .into_iter()
+                .tuple_windows()
+                .find(|&(a, b)| !trial.is_built(a, b))
+                .filter(|&s| segment_cost(trial, s) <= budget)
+        })
+    }
+}
+
+/// Computes an optimal network for its hand up front and builds exactly
+/// that, outwards from its marker, ignoring everyone else.
+#[derive(Debug, Default)]
+pub struct SteinerPlanPlayer {
+    plan: Vec<(Position, Position)>,
+}
+
+impl Player for SteinerPlanPlayer {
+    fn name(&self) -> &str {
+        "steiner-plan"
+    }
+
+    fn choose_start(&mut self, game: &Game, me: usize) -> Position {
+        let hand = game.hand(me);
+        let rails = CostModel::default();
+        // With no tree to build, there's nothing to plan; it just passes
+        let start = match analysis::rank_starts(game.board(), rails, hand) {
+            Ok(ranked) => ranked[0].pos,
+            Err(_) => {
+                self.plan = Vec::new();
+                return hand[0];
+            }
+        };
+        let trees = graph::steiner_trees_exact_usize(game.board(), hand, rails.edge())
+            .expect("ranked but not connected");
+        let tree = trees
+            .iter()
+            .find(|t| t.contains_node(start))
+            .expect("best start on no optimal tree");
+        self.plan = tree.edges().map(|(a, b, _)| (a, b)).collect();
+        self.plan.sort();
+        start
+    }
+
+    fn choose_turn(&mut self, game: &Game, me: usize) -> Vec<(Position, Position)> {
+        let plan = &self.plan;
+        build_turn(game, me, |trial, budget| {
+            let reachable = trial.reachable(me);
+            plan.iter().cloned().find(|&(a, b)| {
+                !trial.is_built(a, b)
+                    && (reachable.contains(&a) || reachable.contains(&b))
+                    && segment_cost(trial, (a, b)) <= budget
+            })
+        })
+    }
+}
+
+/// Starts anywhere and builds any legal segment; a baseline to beat.
+#[derive(Debug)]
+pub struct RandomPlayer {
+    rng: Rng,
+}
+
+impl RandomPlayer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Player for RandomPlayer {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn choose_start(&mut self, game: &Game, _me: usize) -> Position {
+        let mut nodes = game.board().node_ids().collect::<Vec<_>>();
+        nodes.sort();
+        *self.rng.choose(&nodes).unwrap()
+    }
+
+    fn choose_turn(&mut self, game: &Game, me: usize) -> Vec<(Position, Position)> {
+        let rng = &mut self.rng;
+        build_turn(game, me, |trial, budget| {
+            let mut options = trial
+                .reachable(me)
+                .into_iter()
+                .flat_map(|n| trial.board().neighbours(n).map(move |(m, _)| (n, m)))
+                .filter(|&(a, b)| !trial.is_built(a, b) && segment_cost(trial, (a, b)) <= budget)
+                .collect::<Vec<_>>();
+            options.sort();
+            rng.choose(&options).cloned()
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoundResult {
+    /// `None` if everyone passed in a row without anyone finishing.
+    pub winner: Option<usize>,
+    pub turns: usize,
+    /// Rail units each player still needed when the round ended.
+    pub lost: Vec<usize>,
+}
+
+/// Plays one round to completion, with `players[i]` holding `hands[i]`.
+///
+/// Every hand's cities must be connected on `board`.
+pub fn play_round(
+    board: &BoardGraph,
+    hands: Vec<Hand>,
+    players: &mut [Box<dyn Player>],
+) -> RoundResult {
+    assert_eq!(hands.len(), players.len());
+    let mut game = Game::new(board, hands);
+    for (i, p) in players.iter_mut().enumerate() {
+        let start = p.choose_start(&game, i);
+        game.place_start(i, start)
+            .unwrap_or_else(|e| panic!("{} chose a bad start: {:?}", p.name(), e));
+    }
+
+    let mut passes = 0;
+    while !game.is_over() && passes < players.len() {
+        let i = game.current_player();
+        let turn = players[i].choose_turn(&game, i);
+        passes = if turn.is_empty() { passes + 1 } else { 0 };
+        game.play_turn(i, &turn)
+            .unwrap_or_else(|e| panic!("{} made a bad move: {:?}", players[i].name(), e));
+    }
+
+    RoundResult {
+        winner: game.winner(),
+        turns: game.turn(),
+        lost: (0..players.len())
+            .map(|i| game.remaining_cost(i).expect("hand's cities not connected"))
+            .collect(),
+    }
+}
+
+pub type Strategy = fn(u64) -> Box<dyn Player>;
+
+/// Every built-in strategy, each constructed from a seed.
+pub const STRATEGIES: &[Strategy] = &[
+    |_| Box::new(GreedyPlayer),
+    |_| Box::new(SteinerPlanPlayer::default()),
+    |seed| Box::new(RandomPlayer::new(seed)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{make_board, Position as P};
+
+    fn hands() -> Vec<Hand> {
+        vec![
+            vec![P(0, 2), P(2, 3), P(4, 4), P(6, 11), P(11, 0)],
+            vec![P(0, 3), P(3, 1), P(4, 8), P(10, 11), P(13, 2)],
+            vec![P(1, 6), P(3, 9), P(5, 3), P(9, 6), P(12, 9)],
+        ]
+    }
+
+    #[test]
+    fn every_strategy_plays_a_round_to_a_winner() {
+        let board = make_board();
+        for (i, strategy) in STRATEGIES.iter().enumerate() {
+            // `play_round` panics on any illegal start or move
+            let mut players = (0..3).map(strategy).collect::<Vec<_>>();
+            let result = play_round(&board, hands(), &mut players);
+            let winner = result
+                .winner
+                .unwrap_or_else(|| panic!("strategy {} stalled", i));
+            assert_eq!(result.lost[winner], 0);
+        }
+    }
+
+    #[test]
+    fn steiner_plan_builds_no_more_than_planned() {
+        let board = make_board();
+        let mut game = Game::new(&board, hands());
+        let mut players = (0..3)
+            .map(|_| SteinerPlanPlayer::default())
+            .collect::<Vec<_>>();
+        for (i, p) in players.iter_mut().enumerate() {
+            let start = p.choose_start(&game, i);
+            assert!(p.plan.is_empty() || p.plan.iter().any(|&(a, b)| a == start || b == start));
+            game.place_start(i, start).unwrap();
+        }
+
+        let mut built = vec![0; players.len()];
+        while !game.is_over() {
+            let i = game.current_player();
+            let turn = players[i].choose_turn(&game, i);
+            built[i] += turn.iter().map(|&s| segment_cost(&game, s)).sum::<usize>();
+            game.play_turn(i, &turn).unwrap();
+        }
+        for (p, built) in players.iter().zip(built) {
+            let planned = p
+                .plan
+                .iter()
+                .map(|&s| segment_cost(&game, s))
+                .sum::<usize>();
+            assert!(built <= planned, "built {} of a {} plan", built, planned);
+        }
+    }
+}
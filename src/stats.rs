@@ -0,0 +1,234 @@
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
+
+/// Count, mean and central moments, mergeable in any order.
+///
+/// The higher moments merge using Pébay's formulas, the same way `m` does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParallelVariance {
+    n: f64,
+    x: f64,
+    m: f64,
+    m3: f64,
+    m4: f64,
+}
+
+impl ParallelVariance {
+    pub fn mean(self) -> f64 {
+        self.x
+    }
+
+    pub fn sum(self) -> f64 {
+        self.n * self.x
+    }
+
+    pub fn var_corrected(self, c: f64) -> f64 {
+        self.m / (self.n - c)
+    }
+
+    pub fn var_pop(self) -> f64 {
+        self.var_corrected(0.0)
+    }
+
+    pub fn stdev_corrected(self, c: f64) -> f64 {
+        self.var_corrected(c).sqrt()
+    }
+
+    pub fn stdev_pop(self) -> f64 {
+        self.stdev_corrected(0.0)
+    }
+
+    pub fn standardize_corrected(self, x: f64, c: f64) -> f64 {
+        let s = self.stdev_corrected(c);
+        (x - self.mean()) / s
+    }
+
+    pub fn standardize_pop(self, x: f64) -> f64 {
+        self.standardize_corrected(x, 0.0)
+    }
+
+    pub fn count(self) -> f64 {
+        self.n
+    }
+
+    /// Population skewness: zero when symmetric, positive with a long
+    /// expensive tail.
+    pub fn skewness(self) -> f64 {
+        self.n.sqrt() * self.m3 / self.m.powf(1.5)
+    }
+
+    /// Population excess kurtosis: zero for a normal distribution.
+    pub fn kurtosis(self) -> f64 {
+        self.n * self.m4 / (self.m * self.m) - 3.0
+    }
+
+    pub fn merge(a: Self, b: Self) -> Self {
+        if a.n == 0.0 {
+            return b;
+        }
+        if b.n == 0.0 {
+            return a;
+        }
+        let n = a.n + b.n;
+        let x = (a.sum() + b.sum()) / n;
+        let d = b.x - a.x;
+        let m = (a.m + b.m) + d.powi(2) * (a.n * b.n) / n;
+        let m3 = (a.m3 + b.m3)
+            + d.powi(3) * (a.n * b.n) * (a.n - b.n) / (n * n)
+            + 3.0 * d * (a.n * b.m - b.n * a.m) / n;
+        let m4 = (a.m4 + b.m4)
+            + d.powi(4) * (a.n * b.n) * (a.n * a.n - a.n * b.n + b.n * b.n) / (n * n * n)
+            + 6.0 * d * d * (a.n * a.n * b.m + b.n * b.n * a.m) / (n * n)
+            + 4.0 * d * (a.n * b.m3 - b.n * a.m3) / n;
+        Self { n, x, m, m3, m4 }
+    }
+}
+
+impl From<f64> for ParallelVariance {
+    fn from(x: f64) -> Self {
+        Self {
+            n: 1.0,
+            x,
+            ..Self::default()
+        }
+    }
+}
+
+impl FromIterator<f64> for ParallelVariance {
+    fn from_iter<I: IntoIterator<Item = f64>>(it: I) -> Self {
+        it.into_iter()
+            .map(|x| x.into())
+            .tree_fold1(ParallelVariance::merge)
+            .unwrap_or_default()
+    }
+}
+
+/// How often each integer cost occurs, for exact quantiles.
+#[derive(Debug, Clone, Default)]
+pub struct Counts {
+    n: usize,
+    counts: BTreeMap<usize, usize>,
+}
+
+impl Counts {
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    pub fn histogram(&self) -> &BTreeMap<usize, usize> {
+        &self.counts
+    }
+
+    /// The smallest cost at least a fraction `p` of the values are no more
+    /// than (the nearest-rank definition), or `None` if there are no values.
+    pub fn quantile(&self, p: f64) -> Option<usize> {
+        let rank = ((p * self.n as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (&x, &k) in &self.counts {
+            seen += k;
+            if seen >= rank {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    pub fn fraction_at_most(&self, x: usize) -> f64 {
+        let k = self.counts.range(..=x).map(|x| x.1).sum::<usize>();
+        k as f64 / self.n as f64
+    }
+
+    pub fn merge(mut a: Self, b: Self) -> Self {
+        a.n += b.n;
+        for (x, k) in b.counts {
+            *a.counts.entry(x).or_default() += k;
+        }
+        a
+    }
+}
+
+impl From<usize> for Counts {
+    fn from(x: usize) -> Self {
+        Self {
+            n: 1,
+            counts: std::iter::once((x, 1)).collect(),
+        }
+    }
+}
+
+/// Moments and exact counts of integer costs together.
+#[derive(Debug, Clone, Default)]
+pub struct Distribution {
+    pub moments: ParallelVariance,
+    pub counts: Counts,
+}
+
+impl Distribution {
+    pub fn merge(a: Self, b: Self) -> Self {
+        Self {
+            moments: ParallelVariance::merge(a.moments, b.moments),
+            counts: Counts::merge(a.counts, b.counts),
+        }
+    }
+}
+
+impl From<usize> for Distribution {
+    fn from(x: usize) -> Self {
+        Self {
+            moments: (x as f64).into(),
+            counts: x.into(),
+        }
+    }
+}
+
+impl FromIterator<usize> for Distribution {
+    fn from_iter<I: IntoIterator<Item = usize>>(it: I) -> Self {
+        it.into_iter()
+            .map(Distribution::from)
+            .tree_fold1(Distribution::merge)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_moments_match_direct() {
+        let xs = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0, 5.0, 3.0, 5.0];
+        let n = xs.len() as f64;
+        let mean = xs.iter().sum::<f64>() / n;
+        let moment = |k| xs.iter().map(|x| (x - mean).powi(k)).sum::<f64>() / n;
+        let skewness = moment(3) / moment(2).powf(1.5);
+        let kurtosis = moment(4) / moment(2).powi(2) - 3.0;
+
+        let a = xs[..4].iter().cloned().collect::<ParallelVariance>();
+        let b = xs[4..].iter().cloned().collect::<ParallelVariance>();
+        let merged = ParallelVariance::merge(a, b);
+        assert!((merged.mean() - mean).abs() < 1e-12);
+        assert!((merged.var_pop() - moment(2)).abs() < 1e-12);
+        assert!((merged.skewness() - skewness).abs() < 1e-12);
+        assert!((merged.kurtosis() - kurtosis).abs() < 1e-12);
+    }
+
+    #[test]
+    fn quantiles() {
+        let d = [5, 1, 4, 2, 3, 3, 3, 4, 2, 1]
+            .iter()
+            .cloned()
+            .collect::<Distribution>();
+        assert_eq!(d.counts.quantile(0.1), Some(1));
+        assert_eq!(d.counts.quantile(0.25), Some(2));
+        assert_eq!(d.counts.quantile(0.5), Some(3));
+        assert_eq!(d.counts.quantile(0.9), Some(4));
+        assert_eq!(d.counts.quantile(1.0), Some(5));
+        assert_eq!(d.counts.fraction_at_most(2), 0.4);
+        assert_eq!(Counts::default().quantile(0.5), None);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::data::{Color, Cost, Position};
+use crate::graph::UnGraph;
+use crate::map::Map;
+use crate::pairs::Interactions;
+use std::fmt::Write;
+use std::hash::BuildHasher;
+
+/// Pixels per edge length.
+const SCALE: f64 = 40.0;
+const MARGIN: f64 = 60.0;
+
+/// The box's colours for Transamerica's five; any other colour is drawn as
+/// the CSS colour of its name.
+pub fn color_hex(c: Color) -> &'static str {
+    match c {
+        Color::GREEN => "#2e8b57",
+        Color::YELLOW => "#e0b000",
+        Color::RED => "#d62728",
+        Color::BLUE => "#1f77b4",
+        Color::ORANGE => "#ff7f0e",
+        _ => c.name(),
+    }
+}
+
+/// A hand drawn over the board: its cities, track and Steiner points.
+pub struct Overlay<'a, S> {
+    pub title: String,
+    pub hand: &'a [Position],
+    pub track: &'a UnGraph<Position, (), (), S>,
+    pub steiner_points: &'a [Position],
+}
+
+struct Frame {
+    min_x: f64,
+    max_y: f64,
+}
+
+impl Frame {
+    fn point(&self, p: Position) -> (f64, f64) {
+        let (x, y) = p.planar();
+        (
+            MARGIN + (x - self.min_x) * SCALE,
+            MARGIN + (self.max_y - y) * SCALE,
+        )
+    }
+}
+
+/// Draws the board, with `Cost::Two` segments (rivers and mountains) thick
+/// and dashed, and cities coloured; dashed outlines mark the cities left out
+/// of 2–3 player games.
+pub fn render<S>(map: &Map, overlay: Option<&Overlay<S>>) -> String
+where
+    S: BuildHasher + Default,
+{
+    let board = &map.board;
+    let planar = board.node_ids().map(Position::planar).collect::<Vec<_>>();
+    let min_x = planar.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let max_x = planar.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = planar.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let max_y = planar.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+    let frame = Frame { min_x, max_y };
+    let width = 2.0 * MARGIN + (max_x - min_x) * SCALE;
+    let height = 2.0 * MARGIN + (max_y - min_y) * SCALE;
+
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="sans-serif">"#,
+        width, height, width, height
+    );
+    let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    let mut edges = board.edges().collect::<Vec<_>>();
+    edges.sort_by_key(|x| (x.0, x.1));
+    for (a, b, e) in edges {
+        let ((x1, y1), (x2, y2)) = (frame.point(a), frame.point(b));
+        let style = match e.cost {
+            Cost::Two => r##"stroke="#8b5a2b" stroke-width="3" stroke-dasharray="6 3""##,
+            Cost::Zero => r##"stroke="#bbbbbb" stroke-width="1" stroke-dasharray="1 3""##,
+            _ => r##"stroke="#bbbbbb" stroke-width="1""##,
+        };
+        let _ = writeln!(
+            out,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" {}/>"#,
+            x1, y1, x2, y2, style
+        );
+    }
+
+    if let Some(overlay) = overlay {
+        let mut track = overlay.track.edges().collect::<Vec<_>>();
+        track.sort_by_key(|x| (x.0, x.1));
+        for (a, b, _) in track {
+            let ((x1, y1), (x2, y2)) = (frame.point(a), frame.point(b));
+            let _ = writeln!(
+                out,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="black" stroke-width="5" stroke-linecap="round"/>"#,
+                x1, y1, x2, y2
+            );
+        }
+        for &p in overlay.steiner_points {
+            let (x, y) = frame.point(p);
+            let _ = writeln!(
+                out,
+                r#"<rect x="{:.1}" y="{:.1}" width="10" height="10" transform="rotate(45 {:.1} {:.1})" fill="black"/>"#,
+                x - 5.0,
+                y - 5.0,
+                x,
+                y
+            );
+        }
+        let _ = writeln!(
+            out,
+            r#"<text x="{:.0}" y="{:.0}" font-size="18">{}</text>"#,
+            MARGIN / 2.0,
+            MARGIN / 2.0,
+            escape(&overlay.title)
+        );
+    }
+
+    let mut nodes = board.node_ids().collect::<Vec<_>>();
+    nodes.sort();
+    for n in nodes {
+        if map.city_at(n).is_none() {
+            let (x, y) = frame.point(n);
+            let _ = writeln!(
+                out,
+                r##"<circle cx="{:.1}" cy="{:.1}" r="2" fill="#888888"/>"##,
+                x, y
+            );
+        }
+    }
+
+    for c in &map.cities {
+        let (x, y) = frame.point(c.pos);
+        let in_hand = overlay.is_some_and(|o| o.hand.contains(&c.pos));
+        let (r, stroke) = if in_hand {
+            (10.0, r#"stroke="black" stroke-width="3""#)
+        } else {
+            (7.0, r#"stroke="black" stroke-width="1""#)
+        };
+        let dash = if c.dashed {
+            r#" stroke-dasharray="3 2""#
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{}" fill="{}" {}{}/>"#,
+            x,
+            y,
+            r,
+            color_hex(c.color),
+            stroke,
+            dash
+        );
+        let _ = writeln!(
+            out,
+            r#"<text x="{:.1}" y="{:.1}" font-size="{}" text-anchor="middle"{}>{}</text>"#,
+            x,
+            y - r - 4.0,
+            if in_hand { 14 } else { 11 },
+            if in_hand {
+                r#" font-weight="bold""#
+            } else {
+                ""
+            },
+            escape(c.name)
+        );
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Pixels per cell of the `heatmap`, and room for its labels.
+const CELL: f64 = 16.0;
+const LABEL: f64 = 120.0;
+
+/// A matrix of pair effects: blue for pairs cheaper together than their
+/// cities' means predict, red for dearer, white for neither.  The diagonal
+/// shows each city's colour; hovering over a cell gives the numbers.
+pub fn heatmap(x: &Interactions) -> String {
+    let n = x.cities.len() as f64;
+    let size = LABEL + n * CELL + MARGIN / 2.0;
+    let scale = x
+        .unique_pairs()
+        .map(|(_, _, p)| p.effect.abs())
+        .fold(0.0, f64::max)
+        .max(f64::EPSILON);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="sans-serif" font-size="11">"#,
+        size, size, size, size
+    );
+    let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    for (i, c) in x.cities.iter().enumerate() {
+        let offset = LABEL + (i as f64 + 0.5) * CELL + 4.0;
+        let _ = writeln!(
+            out,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+            LABEL - 4.0,
+            offset,
+            escape(c.name)
+        );
+        let _ = writeln!(
+            out,
+            r#"<text x="{:.1}" y="{:.1}" transform="rotate(-90 {:.1} {:.1})">{}</text>"#,
+            offset,
+            LABEL - 4.0,
+            offset,
+            LABEL - 4.0,
+            escape(c.name)
+        );
+    }
+
+    for (i, row) in x.pairs.iter().enumerate() {
+        for (j, pair) in row.iter().enumerate() {
+            let (a, b) = (x.cities[i], x.cities[j]);
+            let (fill, title) = match pair {
+                Some(p) => (
+                    diverging(p.effect / scale),
+                    format!(
+                        "{} + {}: mean {:.2} over {} hands, effect {:+.2}",
+                        a.name, b.name, p.mean, p.hands, p.effect
+                    ),
+                ),
+                None if i == j => (
+                    color_hex(a.color).to_owned(),
+                    format!("{}: mean {:.2}", a.name, x.means[i]),
+                ),
+                None => (
+                    "#eeeeee".to_owned(),
+                    format!("{} and {} are both {:?}", a.name, b.name, a.color),
+                ),
+            };
+            let _ = writeln!(
+                out,
+                r#"<rect x="{:.1}" y="{:.1}" width="{}" height="{}" fill="{}"><title>{}</title></rect>"#,
+                LABEL + j as f64 * CELL,
+                LABEL + i as f64 * CELL,
+                CELL,
+                CELL,
+                fill,
+                escape(&title)
+            );
+        }
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// White at 0, through to full blue at -1 and full red at 1.
+fn diverging(t: f64) -> String {
+    let t = t.clamp(-1.0, 1.0);
+    let fade = |full: f64| (255.0 - (255.0 - full) * t.abs()).round() as u8;
+    let (r, g, b) = if t < 0.0 {
+        (31.0, 119.0, 180.0)
+    } else {
+        (214.0, 39.0, 40.0)
+    };
+    format!("#{:02x}{:02x}{:02x}", fade(r), fade(g), fade(b))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_file;
+    use crate::game::Network;
+
+    #[test]
+    fn small_board() {
+        let map = board_file::parse(
+            "small",
+            "column 0: 111 1..\n\
+             column 1: 2..\n\
+             city 0,0 red Denver\n\
+             city 1,1 blue Dallas & Fort Worth\n\
+             city 2,0 red dashed Detroit\n",
+        )
+        .unwrap();
+        let count = |svg: &str, x| svg.matches(x).count();
+
+        let plain = render::<fnv::FnvBuildHasher>(&map, None);
+        assert!(plain.starts_with("<svg "));
+        assert!(plain.ends_with("</svg>\n"));
+        assert_eq!(count(&plain, "<line "), 5);
+        assert_eq!(count(&plain, r#"stroke-dasharray="6 3""#), 1);
+        // The two junctions, then the cities with Detroit's outline dashed
+        assert_eq!(count(&plain, r#"r="2""#), 2);
+        assert_eq!(count(&plain, r#"r="7""#), 3);
+        assert_eq!(count(&plain, r#"stroke-dasharray="3 2""#), 1);
+        assert!(plain.contains(">Dallas &amp; Fort Worth</text>"));
+        assert!(!plain.contains("<text x=\"30\""));
+
+        let mut track = Network::with_capacity(0, 0);
+        for &p in &[Position(0, 0), Position(1, 1), Position(0, 1)] {
+            track.try_add_node(p, ());
+        }
+        track.try_add_edge(Position(0, 0), Position(0, 1), ());
+        track.try_add_edge(Position(0, 1), Position(1, 1), ());
+        let hand = [Position(0, 0), Position(1, 1)];
+        let overlay = Overlay {
+            title: "Denver <-> Dallas".to_owned(),
+            hand: &hand,
+            track: &track,
+            steiner_points: &[Position(0, 1)],
+        };
+        let drawn = render(&map, Some(&overlay));
+        assert_eq!(count(&drawn, "<line "), 7);
+        assert_eq!(count(&drawn, r#"stroke="black" stroke-width="5""#), 2);
+        assert_eq!(count(&drawn, "transform=\"rotate(45 "), 1);
+        assert_eq!(count(&drawn, r#"r="10""#), 2);
+        assert_eq!(count(&drawn, r#"r="7""#), 1);
+        assert!(drawn.contains(">Denver &lt;-&gt; Dallas</text>"));
+        assert!(drawn.ends_with("</svg>\n"));
+    }
+}
@@ -22,7 +22,7 @@ pub struct SimpleDisjointSet(Vec<Entry>, usize);
 
 impl SimpleDisjointSet {
     pub fn new(n: usize) -> Self {
-        SimpleDisjointSet((0..n).map(|i| Entry::new(i)).collect(), n)
+        SimpleDisjointSet((0..n).map(Entry::new).collect(), n)
     }
 
     pub fn node_count(&self) -> usize {
@@ -0,0 +1,167 @@
+use crate::data::{City, Cost, Position};
+use crate::map::Map;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// Nodes cut off from the largest connected part of the board.
+    Disconnected(Vec<Position>),
+    CityOffBoard(City),
+    /// A city with no track to or from it.
+    IsolatedCity(City),
+    SharedCityPosition(City, City),
+    /// Free track, which no published map has.
+    FreeTrack(Position, Position),
+    /// An edge that isn't one step in any `Direction`.
+    NotAdjacent(Position, Position),
+    /// An edge that can never be built, which should have been left out.
+    Impassable(Position, Position),
+    /// Neighbouring nodes with no edge between them, usually a `.` typed
+    /// for a cost.
+    MissingEdge(Position, Position),
+}
+
+impl Problem {
+    /// Where the problem is, for sorting: its first node.
+    fn position(&self) -> Position {
+        match self {
+            Problem::Disconnected(nodes) => nodes[0],
+            Problem::CityOffBoard(c) | Problem::IsolatedCity(c) => c.pos,
+            Problem::SharedCityPosition(_, c) => c.pos,
+            Problem::FreeTrack(a, _)
+            | Problem::NotAdjacent(a, _)
+            | Problem::Impassable(a, _)
+            | Problem::MissingEdge(a, _) => *a,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Disconnected(nodes) => write!(
+                f,
+                "{} nodes are cut off from the rest of the board: {:?}",
+                nodes.len(),
+                nodes
+            ),
+            Problem::CityOffBoard(c) => write!(f, "{} at {:?} is not a node", c.name, c.pos),
+            Problem::IsolatedCity(c) => write!(f, "{} at {:?} has no track", c.name, c.pos),
+            Problem::SharedCityPosition(a, b) => {
+                write!(f, "{} and {} are both at {:?}", a.name, b.name, a.pos)
+            }
+            Problem::FreeTrack(a, b) => write!(f, "{:?} - {:?} costs nothing", a, b),
+            Problem::NotAdjacent(a, b) => write!(f, "{:?} - {:?} are not neighbours", a, b),
+            Problem::Impassable(a, b) => write!(f, "{:?} - {:?} can't be built", a, b),
+            Problem::MissingEdge(a, b) => {
+                write!(f, "{:?} - {:?} are neighbours with no track", a, b)
+            }
+        }
+    }
+}
+
+/// Checks a map for transcription mistakes, returning every problem found,
+/// in order of position (problems at the same place in the order above).
+pub fn validate(map: &Map) -> Vec<Problem> {
+    let board = &map.board;
+    let mut problems = Vec::new();
+
+    let mut components = Vec::<Vec<Position>>::new();
+    let mut seen = fnv::FnvHashSet::default();
+    let mut nodes = board.node_ids().collect::<Vec<_>>();
+    nodes.sort();
+    for &n in &nodes {
+        if !seen.insert(n) {
+            continue;
+        }
+        let mut component = vec![n];
+        let mut stack = vec![n];
+        while let Some(m) = stack.pop() {
+            for (o, _) in board.neighbours(m) {
+                if seen.insert(o) {
+                    component.push(o);
+                    stack.push(o);
+                }
+            }
+        }
+        component.sort();
+        components.push(component);
+    }
+    // The largest part is the board; anything else was cut off by mistake
+    if let Some(largest) = (0..components.len()).max_by_key(|&i| components[i].len()) {
+        components.remove(largest);
+    }
+    problems.extend(components.into_iter().map(Problem::Disconnected));
+
+    let mut cities = map.cities.clone();
+    cities.sort_by_key(|c| c.pos);
+    for (i, c) in cities.iter().enumerate() {
+        if !board.contains_node(c.pos) {
+            problems.push(Problem::CityOffBoard(*c));
+        } else if board.neighbours(c.pos).len() == 0 {
+            problems.push(Problem::IsolatedCity(*c));
+        }
+        if let Some(other) = cities[..i].iter().find(|x| x.pos == c.pos) {
+            problems.push(Problem::SharedCityPosition(*other, *c));
+        }
+    }
+
+    let mut edges = board.edges().collect::<Vec<_>>();
+    edges.sort_by_key(|x| (x.0, x.1));
+    for (a, b, e) in edges {
+        if !a.is_adjacent(b) {
+            problems.push(Problem::NotAdjacent(a, b));
+        }
+        match e.cost {
+            Cost::Zero => problems.push(Problem::FreeTrack(a, b)),
+            Cost::Inf => problems.push(Problem::Impassable(a, b)),
+            Cost::One | Cost::Two => {}
+        }
+    }
+
+    for &n in &nodes {
+        for m in n.forward_neighbours() {
+            if board.contains_node(m) && !board.contains_edge(n, m) {
+                problems.push(Problem::MissingEdge(n, m));
+            }
+        }
+    }
+
+    problems.sort_by_key(Problem::position);
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_file;
+    use crate::data::Position as P;
+
+    #[test]
+    fn built_in_maps_are_valid() {
+        for &(name, _) in crate::map::BUILT_IN {
+            assert_eq!(validate(&Map::built_in(name).unwrap()), []);
+        }
+    }
+
+    #[test]
+    fn finds_problems() {
+        let text = "column 0: 111 0.. ..1\ncolumn 5: 1..\ncity 0,0 red A\ncity 0,3 blue B";
+        let mut map = board_file::parse("test", text).unwrap();
+        let edge = *map.board.get_edge(P(0, 0), P(1, 0)).unwrap();
+        map.board.add_node(P(2, 2), ());
+        map.board.add_edge(P(0, 0), P(2, 2), edge);
+        assert_eq!(
+            validate(&map),
+            [
+                Problem::NotAdjacent(P(0, 0), P(2, 2)),
+                Problem::FreeTrack(P(0, 1), P(1, 1)),
+                Problem::MissingEdge(P(0, 1), P(0, 2)),
+                Problem::Disconnected(vec![P(0, 2), P(0, 3)]),
+                Problem::MissingEdge(P(1, 0), P(1, 1)),
+                Problem::MissingEdge(P(1, 1), P(2, 2)),
+                Problem::Disconnected(vec![P(5, 0), P(6, 0)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::bucket_queue::BucketQueue;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::ops::{Add, AddAssign};
+
+/// An edge or path cost for the generic graph algorithms.
+///
+/// `Default` is the zero.  Each weight picks the priority queue its
+/// searches use, which is how `usize` gets a `BucketQueue` while everything
+/// else gets a binary heap.
+pub trait Weight: Copy + Ord + Default + Add<Output = Self> + AddAssign + fmt::Debug {
+    type Queue<T: Ord>: PriorityQueue<Self, T>;
+}
+
+/// A min-queue: `pop` gives the smallest priority first.
+pub trait PriorityQueue<P, T>: Default {
+    fn push(&mut self, priority: P, x: T);
+    /// Like `push`, but the priority may be below the last one popped, as
+    /// Prim's algorithm needs.
+    fn push_any(&mut self, priority: P, x: T);
+    fn pop(&mut self) -> Option<(P, T)>;
+    fn clear(&mut self);
+}
+
+impl<T> PriorityQueue<usize, T> for BucketQueue<T> {
+    fn push(&mut self, priority: usize, x: T) {
+        BucketQueue::push(self, priority, x);
+    }
+
+    fn push_any(&mut self, priority: usize, x: T) {
+        BucketQueue::push_any(self, priority, x);
+    }
+
+    fn pop(&mut self) -> Option<(usize, T)> {
+        BucketQueue::pop(self)
+    }
+
+    fn clear(&mut self) {
+        BucketQueue::clear(self);
+    }
+}
+
+/// A binary heap, for priorities too sparse or fractional for buckets.
+#[derive(Debug, Clone)]
+pub struct HeapQueue<P, T>(BinaryHeap<Reverse<(P, T)>>);
+
+impl<P: Ord, T: Ord> Default for HeapQueue<P, T> {
+    fn default() -> Self {
+        HeapQueue(BinaryHeap::new())
+    }
+}
+
+impl<P: Ord, T: Ord> PriorityQueue<P, T> for HeapQueue<P, T> {
+    fn push(&mut self, priority: P, x: T) {
+        self.0.push(Reverse((priority, x)));
+    }
+
+    fn push_any(&mut self, priority: P, x: T) {
+        self.push(priority, x);
+    }
+
+    fn pop(&mut self) -> Option<(P, T)> {
+        self.0.pop().map(|Reverse(x)| x)
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl Weight for usize {
+    type Queue<T: Ord> = BucketQueue<T>;
+}
+
+impl Weight for u32 {
+    type Queue<T: Ord> = HeapQueue<Self, T>;
+}
+
+impl Weight for u64 {
+    type Queue<T: Ord> = HeapQueue<Self, T>;
+}
+
+/// Thousandths, so that a mountain can cost exactly 1.5.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(pub u64);
+
+impl Fixed {
+    pub const ONE: Fixed = Fixed(1000);
+
+    /// `None` if negative, too big or not a whole number of thousandths
+    /// (give or take floating-point error).
+    pub fn from_f64(x: f64) -> Option<Self> {
+        let scaled = x * Self::ONE.0 as f64;
+        let raw = scaled.round();
+        if raw >= 0.0 && raw < u64::MAX as f64 && (scaled - raw).abs() < 1e-6 {
+            Some(Fixed(raw as u64))
+        } else {
+            None
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::ONE.0 as f64
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Fixed(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+impl Weight for Fixed {
+    type Queue<T: Ord> = HeapQueue<Self, T>;
+}
+
+/// An `f64` ordered by `total_cmp`, for expected costs and the like.
+///
+/// Costs must be finite and not negative, as for any shortest path.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TotalF64(f64);
+
+impl TotalF64 {
+    /// `None` if `x` is negative, infinite or NaN.
+    pub fn new(x: f64) -> Option<Self> {
+        if x.is_finite() && x >= 0.0 {
+            Some(TotalF64(x))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Add for TotalF64 {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        TotalF64(self.0 + other.0)
+    }
+}
+
+impl AddAssign for TotalF64 {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl fmt::Display for TotalF64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Weight for TotalF64 {
+    type Queue<T: Ord> = HeapQueue<Self, T>;
+}